@@ -0,0 +1,166 @@
+//! The [`Backend`] trait that model runtimes implement, and [`FakeBackend`] for tests.
+
+use std::collections::HashMap;
+
+use ndarray::{Array1, Array2, Array3, ArrayD, ArrayView2, Axis, s};
+
+use crate::error::EmbeddingError;
+
+/// A named model input or output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TensorInfo {
+    /// Name the model declares.
+    pub name: String,
+    /// Element type and shape as the backend prints them.
+    pub description: String,
+    /// Declared shape, with -1 for dynamic dimensions, when the model declares one.
+    pub shape: Option<Vec<i64>>,
+}
+
+/// Runs an embedding model on tokenized batches.
+///
+/// [`EmbeddingGenerator`](crate::generator::EmbeddingGenerator) does the tokenization, input
+/// binding and pooling around it, so a backend only has to evaluate the graph.
+pub trait Backend: Send {
+    /// Inputs declared by the model.
+    fn inputs(&self) -> &[TensorInfo];
+
+    /// Outputs declared by the model.
+    fn outputs(&self) -> &[TensorInfo];
+
+    /// Runs the model on `[N, L]` tensors bound to input names and returns output `output`.
+    fn run(
+        &mut self,
+        inputs: &[(&str, ArrayView2<'_, i64>)],
+        output: &str,
+    ) -> Result<ArrayD<f32>, EmbeddingError>;
+}
+
+/// Token embeddings output of [`FakeBackend`].
+pub const FAKE_TOKEN_OUTPUT: &str = "last_hidden_state";
+/// Sentence embeddings output of [`FakeBackend`], when declared.
+pub const FAKE_SENTENCE_OUTPUT: &str = "sentence_embedding";
+
+/// A deterministic stand-in for a BERT-style model, for testing without an ONNX file.
+///
+/// Every token id maps to a fixed pseudo-random vector, which `last_hidden_state` returns per
+/// token. Padding positions get vectors too, so pooling has to mask them out. The optional
+/// `sentence_embedding` output is the mean over the attention mask.
+#[derive(Debug, Clone)]
+pub struct FakeBackend {
+    dimension: usize,
+    inputs: Vec<TensorInfo>,
+    outputs: Vec<TensorInfo>,
+}
+
+impl FakeBackend {
+    /// A model with `input_ids`, `attention_mask` and `token_type_ids` inputs and a
+    /// `last_hidden_state` output of `dimension`-long token embeddings.
+    pub fn new(dimension: usize) -> Self {
+        Self {
+            dimension,
+            inputs: Vec::new(),
+            outputs: vec![tensor_info(
+                FAKE_TOKEN_OUTPUT,
+                "f32",
+                vec![-1, -1, dimension as i64],
+            )],
+        }
+        .with_inputs(&["input_ids", "attention_mask", "token_type_ids"])
+    }
+
+    /// Declares `names` as the model inputs instead. One of them must be `input_ids`.
+    pub fn with_inputs(mut self, names: &[&str]) -> Self {
+        self.inputs = names
+            .iter()
+            .map(|name| tensor_info(name, "i64", vec![-1, -1]))
+            .collect();
+        self
+    }
+
+    /// Also declares a pooled `sentence_embedding` output.
+    pub fn with_sentence_output(mut self) -> Self {
+        let dimension = self.dimension as i64;
+        self.outputs.push(tensor_info(
+            FAKE_SENTENCE_OUTPUT,
+            "f32",
+            vec![-1, dimension],
+        ));
+        self
+    }
+
+    /// The vector `last_hidden_state` holds for every occurrence of token `id`.
+    pub fn token_embedding(&self, id: i64) -> Vec<f32> {
+        (0..self.dimension)
+            .map(|i| {
+                let bits = splitmix64((id as u64) << 32 | i as u64);
+                (bits >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+}
+
+impl Backend for FakeBackend {
+    fn inputs(&self) -> &[TensorInfo] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[TensorInfo] {
+        &self.outputs
+    }
+
+    fn run(
+        &mut self,
+        inputs: &[(&str, ArrayView2<'_, i64>)],
+        output: &str,
+    ) -> Result<ArrayD<f32>, EmbeddingError> {
+        let inputs: HashMap<&str, ArrayView2<i64>> = inputs.iter().copied().collect();
+        if let Some(name) = inputs
+            .keys()
+            .find(|name| !self.inputs.iter().any(|input| input.name == **name))
+        {
+            return Err(EmbeddingError::inference(format!("unknown input {name}")));
+        }
+        let Some(ids) = inputs.get("input_ids") else {
+            return Err(EmbeddingError::inference("missing input input_ids"));
+        };
+        let (rows, length) = ids.dim();
+        let mut tokens = Array3::zeros((rows, length, self.dimension));
+        for ((i, j), id) in ids.indexed_iter() {
+            tokens
+                .slice_mut(s![i, j, ..])
+                .assign(&Array1::from(self.token_embedding(*id)));
+        }
+
+        match output {
+            FAKE_TOKEN_OUTPUT => Ok(tokens.into_dyn()),
+            FAKE_SENTENCE_OUTPUT if self.outputs.len() > 1 => {
+                let mask = match inputs.get("attention_mask") {
+                    Some(mask) => mask.mapv(|m| m as f32),
+                    None => Array2::ones((rows, length)),
+                };
+                let summed = (&tokens * &mask.view().insert_axis(Axis(2))).sum_axis(Axis(1));
+                let counts = mask.sum_axis(Axis(1)).insert_axis(Axis(1));
+                Ok((summed / counts).into_dyn())
+            }
+            _ => Err(EmbeddingError::inference(format!(
+                "unknown output {output}"
+            ))),
+        }
+    }
+}
+
+fn tensor_info(name: &str, element: &str, shape: Vec<i64>) -> TensorInfo {
+    TensorInfo {
+        name: name.to_string(),
+        description: format!("{element}{shape:?}"),
+        shape: Some(shape),
+    }
+}
+
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
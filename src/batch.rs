@@ -0,0 +1,308 @@
+//! Embedding JSONL files of records in chunks, with resumable output.
+
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use ndarray::{ArrayView2, Axis};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::generator::EmbeddingGenerator;
+use crate::index::document_id;
+use crate::store::{EmbeddingStore, StoreWriter};
+
+/// One line of the input file.
+#[derive(Deserialize)]
+struct InputRecord {
+    id: Value,
+    text: String,
+}
+
+/// One embedded record of the output file.
+#[derive(Serialize, Deserialize)]
+struct OutputRecord {
+    id: Value,
+    embedding: Vec<f32>,
+}
+
+/// Output file format of a [`BatchJob`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchFormat {
+    /// One `{"id", "embedding"}` object per line; supports resuming.
+    Jsonl,
+    /// A single JSON array of `{"id", "embedding"}` objects.
+    Json,
+    /// A binary [`EmbeddingStore`]; supports resuming.
+    Store,
+}
+
+/// Embeds a JSONL file of `{"id", "text"}` records in fixed-size chunks.
+pub struct BatchJob {
+    /// JSONL file of `{"id", "text"}` records.
+    pub input: PathBuf,
+    /// File the embeddings are written to.
+    pub output: PathBuf,
+    /// Records embedded per model call.
+    pub chunk_size: usize,
+    /// Format of `output`.
+    pub format: BatchFormat,
+    /// Skip records whose ids are already in the output file and append to it.
+    pub resume: bool,
+}
+
+/// Record counts of a finished [`BatchJob`].
+#[derive(Debug, Default)]
+pub struct BatchSummary {
+    /// Records embedded by this run.
+    pub embedded: usize,
+    /// Records skipped because the output already held them.
+    pub skipped: usize,
+}
+
+impl BatchJob {
+    /// Embeds the input records with `generator` and writes them to the output file, calling
+    /// `progress` with the counts so far after every full chunk.
+    pub fn run(
+        &self,
+        generator: &mut EmbeddingGenerator,
+        mut progress: impl FnMut(&BatchSummary),
+    ) -> Result<BatchSummary> {
+        anyhow::ensure!(self.chunk_size > 0, "chunk size must be at least 1");
+        anyhow::ensure!(
+            !self.resume || self.format != BatchFormat::Json,
+            "only JSONL and store output can be resumed"
+        );
+        let (done, mut writer) = self.open_output(generator)?;
+
+        let input = File::open(&self.input)
+            .with_context(|| format!("Failed to open {}", self.input.display()))?;
+        let mut summary = BatchSummary::default();
+        let mut chunk = Vec::with_capacity(self.chunk_size);
+        for (line_number, line) in BufReader::new(input).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: InputRecord = serde_json::from_str(&line).with_context(|| {
+                format!(
+                    "{}:{}: invalid record",
+                    self.input.display(),
+                    line_number + 1
+                )
+            })?;
+            if done.contains(&writer.key(&record.id)) {
+                summary.skipped += 1;
+                continue;
+            }
+            chunk.push(record);
+            if chunk.len() == self.chunk_size {
+                summary.embedded += embed_chunk(generator, &mut chunk, &mut writer)?;
+                progress(&summary);
+            }
+        }
+        if !chunk.is_empty() {
+            summary.embedded += embed_chunk(generator, &mut chunk, &mut writer)?;
+        }
+        writer.finish()?;
+        Ok(summary)
+    }
+
+    /// Opens the output file, returning the keys of the records it already holds when resuming.
+    fn open_output(
+        &self,
+        generator: &mut EmbeddingGenerator,
+    ) -> Result<(HashSet<String>, OutputWriter)> {
+        if self.format == BatchFormat::Store {
+            let model_id = generator.config().model_id();
+            let dimension = generator.dimension()?;
+            return if self.resume {
+                let done = if self.output.exists() {
+                    EmbeddingStore::open(&self.output)?
+                        .ids()
+                        .iter()
+                        .cloned()
+                        .collect()
+                } else {
+                    HashSet::new()
+                };
+                let writer = StoreWriter::append(&self.output, &model_id, dimension)?;
+                Ok((done, OutputWriter::Store(writer)))
+            } else {
+                let writer = StoreWriter::create(&self.output, &model_id, dimension)?;
+                Ok((HashSet::new(), OutputWriter::Store(writer)))
+            };
+        }
+
+        let done = if self.resume {
+            recover_jsonl_output(&self.output)?
+        } else {
+            HashSet::new()
+        };
+        let output = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(self.resume)
+            .truncate(!self.resume)
+            .open(&self.output)
+            .with_context(|| format!("Failed to open {}", self.output.display()))?;
+        let writer = RecordWriter::new(BufWriter::new(output), self.format)?;
+        Ok((done, OutputWriter::Records(writer)))
+    }
+}
+
+/// Embeds and writes `chunk`, leaving it empty. Returns the number of records written.
+fn embed_chunk(
+    generator: &mut EmbeddingGenerator,
+    chunk: &mut Vec<InputRecord>,
+    writer: &mut OutputWriter,
+) -> Result<usize> {
+    let texts: Vec<String> = chunk.iter().map(|r| r.text.clone()).collect();
+    let embeddings = generator
+        .generate_embeddings(&texts)
+        .context("Failed to generate embeddings")?;
+    let ids: Vec<Value> = chunk.drain(..).map(|record| record.id).collect();
+    writer.write(ids, embeddings.view())?;
+    Ok(texts.len())
+}
+
+enum OutputWriter {
+    Records(RecordWriter<BufWriter<File>>),
+    Store(StoreWriter),
+}
+
+impl OutputWriter {
+    /// How a record id is matched against the ids already in the output.
+    fn key(&self, id: &Value) -> String {
+        match self {
+            OutputWriter::Records(_) => id.to_string(),
+            OutputWriter::Store(_) => document_id(id),
+        }
+    }
+
+    fn write(&mut self, ids: Vec<Value>, embeddings: ArrayView2<f32>) -> Result<()> {
+        match self {
+            OutputWriter::Records(writer) => {
+                for (id, row) in ids.into_iter().zip(embeddings.axis_iter(Axis(0))) {
+                    writer.write(&OutputRecord {
+                        id,
+                        embedding: row.to_vec(),
+                    })?;
+                }
+                // Flush per chunk so an interrupted run only loses the chunk in flight.
+                writer.flush()
+            }
+            OutputWriter::Store(writer) => {
+                let ids: Vec<String> = ids.iter().map(document_id).collect();
+                writer.write(&ids, embeddings)
+            }
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            OutputWriter::Records(writer) => writer.finish(),
+            OutputWriter::Store(writer) => writer.finish(),
+        }
+    }
+}
+
+/// Returns the ids already present in a JSONL output file, truncating a trailing partial line
+/// left behind by an interrupted run.
+fn recover_jsonl_output(path: &Path) -> Result<HashSet<String>> {
+    let mut done = HashSet::new();
+    let Ok(file) = File::open(path) else {
+        return Ok(done);
+    };
+    let mut reader = BufReader::new(file);
+    let mut valid_len = 0u64;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        if read == 0 || !line.ends_with('\n') {
+            break;
+        }
+        match serde_json::from_str::<OutputRecord>(&line) {
+            Ok(record) => {
+                done.insert(record.id.to_string());
+                valid_len += read as u64;
+            }
+            Err(_) => break,
+        }
+    }
+    OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_len(valid_len)?;
+    Ok(done)
+}
+
+struct RecordWriter<W: Write> {
+    inner: W,
+    format: BatchFormat,
+    written: usize,
+}
+
+impl<W: Write> RecordWriter<W> {
+    fn new(mut inner: W, format: BatchFormat) -> Result<Self> {
+        if format == BatchFormat::Json {
+            inner.write_all(b"[\n")?;
+        }
+        Ok(Self {
+            inner,
+            format,
+            written: 0,
+        })
+    }
+
+    fn write(&mut self, record: &OutputRecord) -> Result<()> {
+        if self.format == BatchFormat::Json && self.written > 0 {
+            self.inner.write_all(b",\n")?;
+        }
+        serde_json::to_writer(&mut self.inner, record)?;
+        if self.format == BatchFormat::Jsonl {
+            self.inner.write_all(b"\n")?;
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        if self.format == BatchFormat::Json {
+            self.inner.write_all(b"\n]\n")?;
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempPath;
+
+    #[test]
+    fn resume_skips_written_ids_and_drops_partial_line() {
+        let path = TempPath::new("batch-resume.jsonl");
+        std::fs::write(
+            &path,
+            "{\"id\":1,\"embedding\":[0.5]}\n{\"id\":\"b\",\"embedding\":[0.25]}\n{\"id\":3,\"emb",
+        )
+        .unwrap();
+
+        let done = recover_jsonl_output(&path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+
+        assert_eq!(done.len(), 2);
+        assert!(done.contains(&Value::from(1).to_string()));
+        assert!(done.contains(&Value::from("b").to_string()));
+        assert!(contents.ends_with("[0.25]}\n"));
+    }
+}
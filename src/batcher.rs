@@ -0,0 +1,282 @@
+//! Dynamic batching of concurrent server requests into shared model calls.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use ndarray::{Array2, s};
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::generator::EmbeddingGenerator;
+use crate::pool::GeneratorPool;
+
+/// Limits on how long the batcher waits to fill a batch.
+#[derive(Debug, Clone, Copy)]
+pub struct BatcherConfig {
+    /// Texts per model call. A single request larger than this still runs as one batch.
+    pub max_batch_size: usize,
+    /// How long the first request in a batch waits for more to arrive.
+    pub max_wait: Duration,
+    /// Requests that may be queued before callers are pushed back on.
+    pub queue_capacity: usize,
+}
+
+impl Default for BatcherConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 32,
+            max_wait: Duration::from_millis(5),
+            queue_capacity: 1024,
+        }
+    }
+}
+
+/// Embeddings for one request, with the number of tokens the model saw for it.
+pub struct Embedded {
+    /// One row per text, in request order.
+    pub embeddings: Array2<f32>,
+    /// Tokens in the texts, special tokens included and padding excluded.
+    pub tokens: usize,
+}
+
+struct Job {
+    texts: Vec<String>,
+    enqueued: Instant,
+    reply: oneshot::Sender<Result<Embedded>>,
+}
+
+/// Collects concurrent embedding requests into padded batches that run through a single
+/// model call, and fans the rows back out to each caller.
+#[derive(Clone)]
+pub struct Batcher {
+    sender: SyncSender<Job>,
+    metrics: Arc<BatchMetrics>,
+}
+
+impl Batcher {
+    /// Starts one inference thread per generator in `pool`. The threads exit once every clone
+    /// of the returned batcher has been dropped.
+    pub fn spawn(pool: Arc<GeneratorPool>, config: BatcherConfig) -> Result<Self> {
+        anyhow::ensure!(config.max_batch_size > 0, "batch size must be at least 1");
+        let (sender, receiver) = mpsc::sync_channel(config.queue_capacity);
+        let queue = Arc::new(Mutex::new(Queue {
+            receiver,
+            carry: None,
+        }));
+        let metrics = Arc::new(BatchMetrics::default());
+        for worker in 0..pool.size() {
+            let pool = pool.clone();
+            let queue = queue.clone();
+            let metrics = metrics.clone();
+            thread::Builder::new()
+                .name(format!("embedding-batcher-{worker}"))
+                .spawn(move || run_worker(&pool, &queue, config, &metrics))?;
+        }
+        Ok(Self { sender, metrics })
+    }
+
+    /// Queues `texts` and waits for their embeddings.
+    pub async fn embed(&self, texts: Vec<String>) -> Result<Embedded> {
+        let (reply, response) = oneshot::channel();
+        let job = Job {
+            texts,
+            enqueued: Instant::now(),
+            reply,
+        };
+        match self.sender.try_send(job) {
+            Ok(()) => {}
+            // Wait for room off the async runtime rather than blocking one of its threads.
+            Err(TrySendError::Full(job)) => {
+                let sender = self.sender.clone();
+                tokio::task::spawn_blocking(move || sender.send(job))
+                    .await?
+                    .map_err(|_| anyhow::anyhow!("embedding batcher has stopped"))?;
+            }
+            Err(TrySendError::Disconnected(_)) => anyhow::bail!("embedding batcher has stopped"),
+        }
+        response
+            .await
+            .map_err(|_| anyhow::anyhow!("embedding batcher dropped the request"))?
+    }
+
+    /// Totals and averages over the batches run so far.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+}
+
+/// The incoming requests, shared by the workers.
+struct Queue {
+    receiver: Receiver<Job>,
+    /// A request that did not fit into the previous batch; it starts the next one, on whichever
+    /// worker takes the queue next.
+    carry: Option<Job>,
+}
+
+fn run_worker(
+    pool: &GeneratorPool,
+    queue: &Mutex<Queue>,
+    config: BatcherConfig,
+    metrics: &BatchMetrics,
+) {
+    loop {
+        // Only the worker holding the queue collects; the others are busy running batches.
+        let jobs = {
+            let mut queue = queue.lock().unwrap_or_else(|e| e.into_inner());
+            let first = match queue.carry.take() {
+                Some(job) => job,
+                None => match queue.receiver.recv() {
+                    Ok(job) => job,
+                    Err(_) => return,
+                },
+            };
+            let deadline = first.enqueued + config.max_wait;
+            let mut size = first.texts.len();
+            let mut jobs = vec![first];
+            while size < config.max_batch_size {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                match queue.receiver.recv_timeout(timeout) {
+                    Ok(job) if size + job.texts.len() > config.max_batch_size => {
+                        queue.carry = Some(job);
+                        break;
+                    }
+                    Ok(job) => {
+                        size += job.texts.len();
+                        jobs.push(job);
+                    }
+                    Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            jobs
+        };
+        pool.with_generator(|generator| run_batch(generator, jobs, metrics));
+    }
+}
+
+fn run_batch(generator: &mut EmbeddingGenerator, jobs: Vec<Job>, metrics: &BatchMetrics) {
+    let started = Instant::now();
+    let queued: Duration = jobs
+        .iter()
+        .map(|job| started.saturating_duration_since(job.enqueued))
+        .sum();
+    let requests = jobs.len();
+    let texts: usize = jobs.iter().map(|job| job.texts.len()).sum();
+
+    // Encode every request on its own, so that a text one caller got wrong fails only that
+    // caller and not everyone who shared the batch.
+    let mut accepted = Vec::with_capacity(jobs.len());
+    let mut encoded = Vec::with_capacity(jobs.len());
+    for job in jobs {
+        match generator.encode(&job.texts) {
+            Ok(batch) => {
+                accepted.push(job);
+                encoded.push(batch);
+            }
+            Err(e) => {
+                let _ = job.reply.send(Err(e.into()));
+            }
+        }
+    }
+    let result = if accepted.is_empty() {
+        None
+    } else {
+        let batch = generator.concat_encoded(&encoded);
+        Some(
+            generator
+                .embed_encoded(&batch)
+                .map(|embeddings| (embeddings, batch.token_counts())),
+        )
+    };
+    metrics.record(requests, texts, queued, started);
+
+    match result {
+        None => {}
+        Some(Ok((embeddings, token_counts))) => {
+            let mut offset = 0;
+            for job in accepted {
+                let rows = offset..offset + job.texts.len();
+                offset = rows.end;
+                let _ = job.reply.send(Ok(Embedded {
+                    embeddings: embeddings.slice(s![rows.clone(), ..]).to_owned(),
+                    tokens: token_counts[rows].iter().sum(),
+                }));
+            }
+        }
+        Some(Err(e)) => {
+            for job in accepted {
+                let _ = job.reply.send(Err(e.clone().into()));
+            }
+        }
+    }
+}
+
+/// Running totals for the batches processed so far.
+#[derive(Default)]
+struct BatchMetrics {
+    batches: AtomicU64,
+    requests: AtomicU64,
+    texts: AtomicU64,
+    largest_batch: AtomicU64,
+    queue_micros: AtomicU64,
+    inference_micros: AtomicU64,
+}
+
+impl BatchMetrics {
+    fn record(&self, requests: usize, texts: usize, queued: Duration, started: Instant) {
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        self.requests.fetch_add(requests as u64, Ordering::Relaxed);
+        self.texts.fetch_add(texts as u64, Ordering::Relaxed);
+        self.largest_batch
+            .fetch_max(texts as u64, Ordering::Relaxed);
+        self.queue_micros
+            .fetch_add(queued.as_micros() as u64, Ordering::Relaxed);
+        self.inference_micros
+            .fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> MetricsSnapshot {
+        let batches = self.batches.load(Ordering::Relaxed);
+        let requests = self.requests.load(Ordering::Relaxed);
+        let texts = self.texts.load(Ordering::Relaxed);
+        let average = |total: u64, count: u64| {
+            if count == 0 {
+                0.0
+            } else {
+                total as f64 / count as f64
+            }
+        };
+        MetricsSnapshot {
+            batches,
+            requests,
+            texts,
+            largest_batch: self.largest_batch.load(Ordering::Relaxed),
+            mean_batch_size: average(texts, batches),
+            mean_queue_ms: average(self.queue_micros.load(Ordering::Relaxed), requests) / 1000.0,
+            mean_inference_ms: average(self.inference_micros.load(Ordering::Relaxed), batches)
+                / 1000.0,
+        }
+    }
+}
+
+/// Batching metrics, as served at `GET /metrics`.
+#[derive(Debug, Serialize)]
+pub struct MetricsSnapshot {
+    /// Model calls run.
+    pub batches: u64,
+    /// Requests served.
+    pub requests: u64,
+    /// Texts embedded.
+    pub texts: u64,
+    /// Most texts in one model call.
+    pub largest_batch: u64,
+    /// Mean texts per model call.
+    pub mean_batch_size: f64,
+    /// Mean time a request waited before its batch started.
+    pub mean_queue_ms: f64,
+    /// Mean time spent tokenizing and running the model per batch.
+    pub mean_inference_ms: f64,
+}
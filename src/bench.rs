@@ -0,0 +1,73 @@
+//! Throughput measurement of generator pools of different sizes.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::config::EmbeddingConfig;
+use crate::pool::GeneratorPool;
+
+/// Throughput of one pool size.
+#[derive(Debug)]
+pub struct BenchResult {
+    /// Generators in the pool.
+    pub workers: usize,
+    /// Texts embedded in the timed run.
+    pub texts: usize,
+    /// Wall time of the timed run.
+    pub elapsed: Duration,
+}
+
+impl BenchResult {
+    /// Texts embedded per second of wall time.
+    pub fn texts_per_second(&self) -> f64 {
+        self.texts as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Embeds `texts` with a pool of each size in `worker_counts` and measures the wall time.
+///
+/// Each pool first embeds one batch per worker untimed, so session warm-up is not counted.
+pub fn compare_pool_sizes(
+    config: &EmbeddingConfig,
+    worker_counts: &[usize],
+    texts: &[String],
+    batch_size: usize,
+) -> Result<Vec<BenchResult>> {
+    let mut results = Vec::with_capacity(worker_counts.len());
+    for &workers in worker_counts {
+        let pool = GeneratorPool::new(config, workers)?;
+        let warmup = &texts[..texts.len().min(workers * batch_size)];
+        pool.generate_embeddings_parallel(warmup, batch_size)?;
+
+        let started = Instant::now();
+        pool.generate_embeddings_parallel(texts, batch_size)?;
+        results.push(BenchResult {
+            workers,
+            texts: texts.len(),
+            elapsed: started.elapsed(),
+        });
+    }
+    Ok(results)
+}
+
+/// `count` distinct sentences of varying length for benchmarking without an input file.
+pub fn synthetic_corpus(count: usize) -> Vec<String> {
+    const SUBJECTS: [&str; 4] = ["The fox", "A small bird", "The old ship", "Miss Muffet"];
+    const ACTIONS: [&str; 4] = [
+        "ran into the jungle",
+        "zoomed out of the forest",
+        "sailed slowly across the cold northern sea",
+        "sat on a tuffet, eating her curds and whey",
+    ];
+    (0..count)
+        .map(|i| {
+            format!(
+                "{} {} on day {}.",
+                SUBJECTS[i % SUBJECTS.len()],
+                ACTIONS[(i / SUBJECTS.len()) % ACTIONS.len()],
+                i
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,256 @@
+//! An LRU cache of embeddings keyed by text, optionally persisted between runs.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use ndarray::Array2;
+use sha2::{Digest, Sha256};
+
+use crate::config::EmbeddingConfig;
+use crate::store::{EmbeddingStore, StoreWriter};
+
+type Key = [u8; 32];
+
+/// Identifies everything that determines an embedding besides the text: the model and
+/// tokenizer file contents and the settings applied to them.
+pub fn fingerprint(config: &EmbeddingConfig) -> Result<String> {
+    let mut hasher = Sha256::new();
+    for path in [&config.model_path, &config.tokenizer_path] {
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        io::copy(&mut file, &mut hasher)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+    }
+    hasher.update(
+        format!(
+            "{:?}/{:?}/{:?}/{}/{}/{:?}/{}",
+            config.runtime,
+            config.pooling,
+            config.padding,
+            config.max_length,
+            config.normalize,
+            config.output,
+            config.add_special_tokens
+        )
+        .as_bytes(),
+    );
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+struct Entry {
+    embedding: Vec<f32>,
+    last_used: u64,
+}
+
+/// Hit and miss counts since the cache was opened.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    /// Lookups that found an embedding.
+    pub hits: u64,
+    /// Lookups that did not.
+    pub misses: u64,
+}
+
+/// Least-recently-used cache of embeddings, optionally persisted as an [`EmbeddingStore`].
+///
+/// Keys hash the exact text together with the model [`fingerprint`]; whitespace is not
+/// collapsed, as byte-level and SentencePiece tokenizers encode it. A persisted cache written
+/// for another fingerprint is discarded on load, so changing the model or tokenizer never
+/// returns stale embeddings.
+pub struct EmbeddingCache {
+    fingerprint: String,
+    capacity: usize,
+    path: Option<PathBuf>,
+    entries: HashMap<Key, Entry>,
+    /// Keys by last use, oldest first.
+    recency: BTreeMap<u64, Key>,
+    clock: u64,
+    stats: CacheStats,
+    discarded: bool,
+}
+
+impl EmbeddingCache {
+    /// An in-memory cache holding up to `capacity` embeddings.
+    pub fn new(fingerprint: impl Into<String>, capacity: usize) -> Self {
+        Self {
+            fingerprint: fingerprint.into(),
+            capacity,
+            path: None,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+            discarded: false,
+        }
+    }
+
+    /// A cache persisted at `path`, starting from its contents when they were written for the
+    /// same fingerprint. Otherwise it starts empty and [`discarded`](Self::discarded) is set.
+    pub fn load(path: &Path, fingerprint: impl Into<String>, capacity: usize) -> Result<Self> {
+        let mut cache = Self::new(fingerprint, capacity);
+        cache.path = Some(path.to_path_buf());
+        if !path.exists() {
+            return Ok(cache);
+        }
+        let store = EmbeddingStore::open(path)?;
+        if store.model_id() != cache.fingerprint {
+            cache.discarded = true;
+            return Ok(cache);
+        }
+        for (id, row) in store.ids().iter().zip(store.embeddings().rows()) {
+            let Some(key) = parse_key(id) else {
+                anyhow::bail!("{} has an invalid cache key {id:?}", path.display());
+            };
+            cache.put(key, row.to_vec());
+        }
+        Ok(cache)
+    }
+
+    /// Number of cached embeddings.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the cache holds no embeddings.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether [`load`](Self::load) found entries for another fingerprint and dropped them.
+    pub fn discarded(&self) -> bool {
+        self.discarded
+    }
+
+    /// Lookups served from the cache and lookups that missed, so far.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    fn key(&self, text: &str) -> Key {
+        let mut hasher = Sha256::new();
+        hasher.update(self.fingerprint.as_bytes());
+        hasher.update([0]);
+        hasher.update(text.as_bytes());
+        hasher.finalize().into()
+    }
+
+    /// The cached embedding of `text`, marking it as most recently used.
+    pub fn get(&mut self, text: &str) -> Option<&[f32]> {
+        let key = self.key(text);
+        let Some(entry) = self.entries.get_mut(&key) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+        self.clock += 1;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(self.clock, key);
+        entry.last_used = self.clock;
+        Some(&entry.embedding)
+    }
+
+    /// Caches the embedding of `text`, evicting the least recently used entry when full.
+    pub fn insert(&mut self, text: &str, embedding: Vec<f32>) {
+        let key = self.key(text);
+        self.put(key, embedding);
+    }
+
+    fn put(&mut self, key: Key, embedding: Vec<f32>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some(old) = self.entries.remove(&key) {
+            self.recency.remove(&old.last_used);
+        }
+        while self.entries.len() >= self.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+        self.clock += 1;
+        self.recency.insert(self.clock, key);
+        self.entries.insert(
+            key,
+            Entry {
+                embedding,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// Writes the cache to its path, oldest entries first. Does nothing for an in-memory cache.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let Some(dimension) = self.entries.values().next().map(|e| e.embedding.len()) else {
+            return Ok(());
+        };
+        let keys: Vec<&Key> = self.recency.values().collect();
+        let mut rows = Array2::zeros((keys.len(), dimension));
+        for (mut row, key) in rows.rows_mut().into_iter().zip(&keys) {
+            row.assign(&ndarray::aview1(&self.entries[*key].embedding));
+        }
+        let ids: Vec<String> = keys.iter().map(|key| hex(*key)).collect();
+        let mut writer = StoreWriter::create(path, &self.fingerprint, dimension)?;
+        writer.write(&ids, rows.view())?;
+        writer.finish()
+    }
+}
+
+fn parse_key(id: &str) -> Option<Key> {
+    if id.len() != 64 || !id.is_ascii() {
+        return None;
+    }
+    let mut key = [0; 32];
+    for (byte, pair) in key.iter_mut().zip(id.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempPath;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = EmbeddingCache::new("model", 2);
+        cache.insert("a", vec![1.0]);
+        cache.insert("b", vec![2.0]);
+        assert_eq!(cache.get("a"), Some(&[1.0][..]));
+        cache.insert("c", vec![3.0]);
+
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(&[1.0][..]));
+        assert_eq!(cache.get("  a "), None);
+        assert_eq!(cache.get("c"), Some(&[3.0][..]));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats().hits, 3);
+        assert_eq!(cache.stats().misses, 2);
+    }
+
+    #[test]
+    fn persisted_entries_invalidate_when_fingerprint_changes() {
+        let path = TempPath::new("cache.store");
+        let mut cache = EmbeddingCache::load(&path, "model-v1", 10).unwrap();
+        cache.insert("the fox", vec![0.5, 0.25]);
+        cache.save().unwrap();
+
+        let mut reloaded = EmbeddingCache::load(&path, "model-v1", 10).unwrap();
+        assert_eq!(reloaded.get("the fox"), Some(&[0.5, 0.25][..]));
+        assert!(!reloaded.discarded());
+        let mut changed = EmbeddingCache::load(&path, "model-v2", 10).unwrap();
+        assert_eq!(changed.get("the fox"), None);
+        assert!(changed.discarded());
+    }
+}
@@ -0,0 +1,216 @@
+//! Splitting long texts into overlapping token windows and combining their embeddings.
+
+use anyhow::Result;
+use ndarray::{Array1, Array2, ArrayView2, Axis};
+use tokenizers::Tokenizer;
+
+use crate::encoding::special_token_count;
+use crate::generator::EmbeddingGenerator;
+use crate::pooling::l2_normalize;
+
+/// Size of the token windows a long text is split into.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkConfig {
+    /// Tokens per window. Should not exceed the model's maximum sequence length.
+    pub window: usize,
+    /// Tokens shared by consecutive windows, so that no sentence loses its context entirely.
+    pub overlap: usize,
+}
+
+impl Default for ChunkConfig {
+    fn default() -> Self {
+        Self {
+            window: 256,
+            overlap: 32,
+        }
+    }
+}
+
+/// How chunk embeddings are combined into one document embedding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Aggregation {
+    /// Every chunk counts equally.
+    #[default]
+    Mean,
+    /// Chunks are weighted by their token count, so a short trailing chunk counts less.
+    Weighted,
+}
+
+impl std::str::FromStr for Aggregation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mean" => Ok(Aggregation::Mean),
+            "weighted" => Ok(Aggregation::Weighted),
+            other => anyhow::bail!("unknown aggregation {other:?}, expected mean or weighted"),
+        }
+    }
+}
+
+/// One window of a longer text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    /// The part of the source text the chunk covers.
+    pub text: String,
+    /// Character offset of the first character of the chunk in the source text.
+    pub start: usize,
+    /// Character offset one past the last character of the chunk.
+    pub end: usize,
+    /// Number of tokens in the chunk, special tokens excluded.
+    pub tokens: usize,
+}
+
+/// Splits texts into overlapping windows of whole tokens.
+pub struct Chunker {
+    /// The embedding tokenizer with truncation and padding removed.
+    tokenizer: Tokenizer,
+    config: ChunkConfig,
+    /// Tokens per window, after leaving room for the special tokens added when embedding.
+    window: usize,
+}
+
+impl Chunker {
+    /// With `add_special_tokens` set, each window is shortened by the special tokens the
+    /// tokenizer adds around it, so that a chunk still fits in `config.window` tokens.
+    pub fn new(
+        tokenizer: &Tokenizer,
+        config: ChunkConfig,
+        add_special_tokens: bool,
+    ) -> Result<Self> {
+        let window = config
+            .window
+            .saturating_sub(special_token_count(tokenizer, add_special_tokens));
+        anyhow::ensure!(
+            config.overlap < window,
+            "chunk overlap ({}) must be smaller than the window ({} without special tokens)",
+            config.overlap,
+            window
+        );
+        let mut tokenizer = tokenizer.clone();
+        tokenizer.with_padding(None);
+        tokenizer
+            .with_truncation(None)
+            .map_err(|e| anyhow::anyhow!("Failed to disable truncation: {}", e))?;
+        Ok(Self {
+            tokenizer,
+            config,
+            window,
+        })
+    }
+
+    /// Splits `text` into windows of at most `window` tokens, each starting `window - overlap`
+    /// tokens after the previous one. A text that fits in one window comes back whole.
+    pub fn split(&self, text: &str) -> Result<Vec<TextChunk>> {
+        let encoding = self
+            .tokenizer
+            .encode(text, false)
+            .map_err(|e| anyhow::anyhow!("Failed to tokenize input: {}", e))?;
+        let offsets = encoding.get_offsets();
+        let stride = self.window - self.config.overlap;
+
+        let mut chunks = Vec::new();
+        let mut first = 0;
+        while first < offsets.len() {
+            let last = (first + self.window).min(offsets.len()) - 1;
+            let (start, end) = (offsets[first].0, offsets[last].1);
+            chunks.push(TextChunk {
+                text: text[start..end].to_string(),
+                start: text[..start].chars().count(),
+                end: text[..end].chars().count(),
+                tokens: last - first + 1,
+            });
+            if last + 1 == offsets.len() {
+                break;
+            }
+            first += stride;
+        }
+        Ok(chunks)
+    }
+}
+
+/// Splits `text` with `chunker` and embeds every chunk in one batch. Returns the chunks with
+/// a `[chunks, D]` matrix of their embeddings.
+pub fn embed_chunks(
+    generator: &mut EmbeddingGenerator,
+    chunker: &Chunker,
+    text: &str,
+) -> Result<(Vec<TextChunk>, Array2<f32>)> {
+    let chunks = chunker.split(text)?;
+    let texts: Vec<String> = chunks.iter().map(|c| c.text.clone()).collect();
+    let embeddings = generator.generate_embeddings(&texts)?;
+    Ok((chunks, embeddings))
+}
+
+/// Combines chunk embeddings into one document embedding, L2-normalized when `normalize` is
+/// set.
+pub fn aggregate(
+    chunks: &[TextChunk],
+    embeddings: ArrayView2<f32>,
+    aggregation: Aggregation,
+    normalize: bool,
+) -> Result<Array1<f32>> {
+    anyhow::ensure!(
+        !chunks.is_empty(),
+        "cannot aggregate a document without tokens"
+    );
+    let weights: Array1<f32> = match aggregation {
+        Aggregation::Mean => Array1::ones(chunks.len()),
+        Aggregation::Weighted => chunks.iter().map(|c| c.tokens as f32).collect(),
+    };
+    let document = weights.dot(&embeddings) / weights.sum();
+    if !normalize {
+        return Ok(document);
+    }
+    let mut document = document.insert_axis(Axis(0));
+    l2_normalize(&mut document);
+    Ok(document.remove_axis(Axis(0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+    use crate::test_support::word_level_tokenizer;
+
+    #[test]
+    fn splits_into_overlapping_windows_with_char_offsets() {
+        let chunker = Chunker::new(
+            &word_level_tokenizer(),
+            ChunkConfig {
+                window: 3,
+                overlap: 1,
+            },
+            false,
+        )
+        .unwrap();
+        let text = "the fox ran into the jungle";
+
+        let chunks = chunker.split(text).unwrap();
+
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, ["the fox ran", "ran into the", "the jungle"]);
+        assert_eq!((chunks[1].start, chunks[1].end), (8, 20));
+        assert_eq!(chunks[2].tokens, 2);
+        assert!(chunker.split("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn weighted_aggregation_favors_longer_chunks() {
+        let chunk = |tokens| TextChunk {
+            text: String::new(),
+            start: 0,
+            end: 0,
+            tokens,
+        };
+        let chunks = [chunk(3), chunk(1)];
+        let embeddings = array![[1.0, 0.0], [0.0, 1.0]];
+
+        let mean = aggregate(&chunks, embeddings.view(), Aggregation::Mean, false).unwrap();
+        let weighted = aggregate(&chunks, embeddings.view(), Aggregation::Weighted, false).unwrap();
+
+        assert_eq!(mean, array![0.5, 0.5]);
+        assert_eq!(weighted, array![0.75, 0.25]);
+    }
+}
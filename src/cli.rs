@@ -0,0 +1,851 @@
+use std::io::{self, BufRead, BufWriter, Write};
+#[cfg(feature = "server")]
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use ndarray::{Array2, Axis};
+use serde_json::json;
+
+use onnx_inference::batch::{BatchFormat, BatchJob};
+#[cfg(feature = "server")]
+use onnx_inference::batcher::BatcherConfig;
+use onnx_inference::bench;
+use onnx_inference::cache::{self, EmbeddingCache};
+use onnx_inference::chunking::{self, Aggregation, ChunkConfig, embed_chunks};
+use onnx_inference::compare;
+use onnx_inference::config::{EmbeddingConfig, OptimizationLevel, Padding, Runtime};
+use onnx_inference::generator::EmbeddingGenerator;
+use onnx_inference::hnsw::{HnswConfig, HnswIndex};
+use onnx_inference::index::{
+    NearestNeighbors, SearchHit, VectorIndex, index_documents, read_documents,
+};
+use onnx_inference::pooling::Pooling;
+use onnx_inference::registry::ModelRegistry;
+#[cfg(feature = "server")]
+use onnx_inference::server;
+use onnx_inference::similarity::similarity;
+use onnx_inference::store::EmbeddingStore;
+
+#[derive(Parser)]
+#[command(about = "Generate sentence embeddings with an ONNX model")]
+pub struct Cli {
+    #[command(flatten)]
+    model: ModelArgs,
+    #[command(flatten)]
+    registry: RegistryArgs,
+    #[command(flatten)]
+    cache: CacheArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct ModelArgs {
+    /// Path to the ONNX model.
+    #[arg(long, global = true, default_value = "./model.onnx")]
+    model: PathBuf,
+    /// Path to the tokenizer.json matching the model.
+    #[arg(long, global = true, default_value = "./tokenizer.json")]
+    tokenizer: PathBuf,
+    /// Inference engine: ort (ONNX Runtime) or tract (pure Rust). The session options below
+    /// apply to ort only. Defaults to ort when it is built in.
+    #[arg(long, global = true)]
+    runtime: Option<Runtime>,
+    /// Graph optimization level: disable, level1, level2 or level3.
+    #[arg(long, global = true, default_value = "level1")]
+    optimization: OptimizationLevel,
+    /// Threads used within a single operator.
+    #[arg(long, global = true, default_value_t = 1)]
+    intra_threads: usize,
+    /// Threads used to run independent operators in parallel. More than one enables parallel
+    /// execution.
+    #[arg(long, global = true, default_value_t = 1)]
+    inter_threads: usize,
+    /// Do not reuse memory allocation plans between runs with the same input shapes.
+    #[arg(long, global = true)]
+    no_memory_pattern: bool,
+    /// Use the system allocator instead of the CPU memory arena.
+    #[arg(long, global = true)]
+    no_cpu_arena: bool,
+    /// Save the optimized graph in this directory and reuse it on later runs.
+    #[arg(long, global = true)]
+    optimized_model_dir: Option<PathBuf>,
+    /// Pooling strategy: mean, cls or max.
+    #[arg(long, global = true, default_value = "mean")]
+    pooling: Pooling,
+    /// L2-normalize the embeddings.
+    #[arg(long, global = true)]
+    normalize: bool,
+    /// Padding: longest (pad to the longest text in the batch) or a fixed token count.
+    #[arg(long, global = true, default_value = "longest")]
+    padding: Padding,
+    /// Truncate texts longer than this many tokens.
+    #[arg(long, global = true, default_value_t = 256)]
+    max_length: usize,
+    /// Reject texts longer than --max-length instead of truncating them.
+    #[arg(long, global = true)]
+    no_truncation: bool,
+    /// Do not add the tokenizer's special tokens, such as [CLS] and [SEP], around each text.
+    #[arg(long, global = true)]
+    no_special_tokens: bool,
+    /// Model output holding the embeddings, e.g. last_hidden_state (pooled) or
+    /// sentence_embedding (used as is). Defaults to the first output.
+    #[arg(long, global = true)]
+    model_output: Option<String>,
+}
+
+impl ModelArgs {
+    fn embedding_config(&self) -> EmbeddingConfig {
+        let config = EmbeddingConfig::default()
+            .with_model_path(&self.model)
+            .with_tokenizer_path(&self.tokenizer)
+            .with_optimization_level(self.optimization)
+            .with_intra_threads(self.intra_threads)
+            .with_inter_threads(self.inter_threads)
+            .with_memory_pattern(!self.no_memory_pattern)
+            .with_cpu_arena(!self.no_cpu_arena)
+            .with_pooling(self.pooling)
+            .with_normalization(self.normalize)
+            .with_padding(self.padding)
+            .with_max_length(self.max_length)
+            .with_truncation(!self.no_truncation)
+            .with_special_tokens(!self.no_special_tokens);
+        let config = match self.runtime {
+            Some(runtime) => config.with_runtime(runtime),
+            None => config,
+        };
+        let config = match &self.model_output {
+            Some(output) => config.with_output(output),
+            None => config,
+        };
+        match &self.optimized_model_dir {
+            Some(dir) => config.with_optimized_model_dir(dir),
+            None => config,
+        }
+    }
+}
+
+#[derive(Args)]
+struct RegistryArgs {
+    /// JSON model registry; --model-name picks one of its models instead of --model and
+    /// --tokenizer.
+    #[arg(long, global = true)]
+    models: Option<PathBuf>,
+    /// Registry model to use. Defaults to the registry's default model.
+    #[arg(long, global = true, requires = "models")]
+    model_name: Option<String>,
+}
+
+impl RegistryArgs {
+    /// The registry file with --model-name as its default, or a registry of the single model
+    /// configured by `base`.
+    fn registry(&self, base: &EmbeddingConfig, workers: usize) -> Result<ModelRegistry> {
+        match &self.models {
+            Some(path) => {
+                let registry = ModelRegistry::from_file(path, base, workers)?;
+                match &self.model_name {
+                    Some(name) => registry.with_default(name),
+                    None => Ok(registry),
+                }
+            }
+            None => {
+                let name = base
+                    .model_path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "model".to_string());
+                ModelRegistry::single(&name, base.clone(), workers)
+            }
+        }
+    }
+}
+
+#[derive(Args)]
+struct CacheArgs {
+    /// Cache embeddings in this file and reuse them across runs. Entries written for a
+    /// different model or tokenizer are discarded.
+    #[arg(long, global = true)]
+    cache: Option<PathBuf>,
+    /// Most embeddings kept in the cache; the least recently used are evicted first.
+    #[arg(long, global = true, default_value_t = 100_000)]
+    cache_size: usize,
+}
+
+impl CacheArgs {
+    fn open(&self, config: &EmbeddingConfig) -> Result<Option<EmbeddingCache>> {
+        let Some(path) = &self.cache else {
+            return Ok(None);
+        };
+        let fingerprint = cache::fingerprint(config)?;
+        let cache = EmbeddingCache::load(path, fingerprint, self.cache_size)?;
+        if cache.discarded() {
+            eprintln!(
+                "discarding embedding cache {}: the model or tokenizer changed",
+                path.display()
+            );
+        }
+        Ok(Some(cache))
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Embed texts and print one vector per text.
+    Embed {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+        format: OutputFormat,
+    },
+    /// Score texts against a query, or every pair of texts when no query is given.
+    Similarity {
+        /// Text to compare every input text against.
+        #[arg(long)]
+        query: Option<String>,
+        #[command(flatten)]
+        input: InputArgs,
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    /// Show the model's inputs, outputs and embedding dimension.
+    Info,
+    /// Split long documents into overlapping token windows and embed each window, printing one
+    /// JSON line per document.
+    Chunk {
+        /// Text files, each embedded as one document.
+        #[arg(required = true)]
+        documents: Vec<PathBuf>,
+        /// Tokens per window. Defaults to --max-length.
+        #[arg(long)]
+        window: Option<usize>,
+        /// Tokens shared by consecutive windows.
+        #[arg(long, default_value_t = ChunkConfig::default().overlap)]
+        overlap: usize,
+        /// Combine the window embeddings into one document embedding: mean or weighted (by
+        /// token count). Prints every window with its character offsets when omitted.
+        #[arg(long)]
+        aggregate: Option<Aggregation>,
+    },
+    /// Embed a JSONL corpus of {"id", "text", ...} records and find the texts closest to a query.
+    Search {
+        /// JSONL corpus file.
+        #[arg(
+            long,
+            short,
+            required_unless_present = "store",
+            conflicts_with = "store"
+        )]
+        corpus: Option<PathBuf>,
+        /// Embedding store written by `batch --format store`, searched instead of a corpus.
+        #[arg(long)]
+        store: Option<PathBuf>,
+        /// Text to search for.
+        #[arg(long, short)]
+        query: String,
+        /// Number of results.
+        #[arg(short, default_value_t = 10)]
+        k: usize,
+        /// Texts per model call while embedding the corpus.
+        #[arg(long, default_value_t = 32)]
+        batch_size: usize,
+        /// Index type: exact (brute force) or hnsw (approximate).
+        #[arg(long, value_enum, default_value_t = IndexKind::Exact)]
+        index: IndexKind,
+        #[command(flatten)]
+        hnsw: HnswArgs,
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    /// Embed a JSONL file of {"id", "text"} records into {"id", "embedding"} records.
+    Batch {
+        /// Input JSONL file.
+        #[arg(long, short)]
+        input: PathBuf,
+        /// Output file.
+        #[arg(long, short)]
+        output: PathBuf,
+        /// Number of records embedded per model call.
+        #[arg(long, default_value_t = 32)]
+        chunk_size: usize,
+        /// Output format: jsonl, json or store.
+        #[arg(long, value_enum, default_value_t = BatchOutputFormat::Jsonl)]
+        format: BatchOutputFormat,
+        /// Skip ids already in the output file and append to it. A JSONL file can be resumed
+        /// after an interrupted run; a store only once a previous run has finished.
+        #[arg(long)]
+        resume: bool,
+    },
+    /// Serve an OpenAI-compatible /v1/embeddings endpoint and a /similarity endpoint over HTTP.
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+        /// Most texts combined into one model call.
+        #[arg(long, default_value_t = 32)]
+        max_batch_size: usize,
+        /// How long a request waits for others to share its batch, in milliseconds.
+        #[arg(long, default_value_t = 5)]
+        max_wait_ms: u64,
+        /// Sessions running batches in parallel.
+        #[arg(long, default_value_t = 1)]
+        workers: usize,
+    },
+    /// Embed a corpus with the model and a candidate, such as an INT8 quantized export of it,
+    /// and report how well their embeddings agree.
+    Compare {
+        /// Candidate ONNX model.
+        #[arg(long)]
+        candidate: PathBuf,
+        /// Tokenizer of the candidate model. Defaults to --tokenizer.
+        #[arg(long)]
+        candidate_tokenizer: Option<PathBuf>,
+        /// File with one text per line. A synthetic corpus is used when omitted.
+        #[arg(long, short)]
+        file: Option<PathBuf>,
+        /// Size of the synthetic corpus.
+        #[arg(long, default_value_t = 256)]
+        count: usize,
+        /// Nearest neighbors compared per text.
+        #[arg(short, default_value_t = 10)]
+        k: usize,
+        /// Texts per model call.
+        #[arg(long, default_value_t = 32)]
+        batch_size: usize,
+    },
+    /// Measure throughput for different session pool sizes.
+    Bench {
+        /// Comma-separated pool sizes to compare.
+        #[arg(long, value_delimiter = ',', default_values_t = [1, 2, 4])]
+        workers: Vec<usize>,
+        /// Texts per model call.
+        #[arg(long, default_value_t = 32)]
+        batch_size: usize,
+        /// File with one text per line. A synthetic corpus is used when omitted.
+        #[arg(long, short)]
+        file: Option<PathBuf>,
+        /// Size of the synthetic corpus.
+        #[arg(long, default_value_t = 512)]
+        count: usize,
+    },
+}
+
+#[derive(Args)]
+struct InputArgs {
+    /// Texts to process. Read one per line from --file or stdin when omitted.
+    texts: Vec<String>,
+    /// File with one text per line.
+    #[arg(long, short)]
+    file: Option<PathBuf>,
+}
+
+impl InputArgs {
+    fn read_texts(&self) -> Result<Vec<String>> {
+        if !self.texts.is_empty() {
+            return Ok(self.texts.clone());
+        }
+        match &self.file {
+            Some(path) => read_text_file(path),
+            None => {
+                let lines: Vec<String> = io::stdin().lock().lines().collect::<io::Result<_>>()?;
+                Ok(non_empty_lines(lines))
+            }
+        }
+    }
+}
+
+/// Reads one text per non-blank line of `path`.
+fn read_text_file(path: &Path) -> Result<Vec<String>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(non_empty_lines(contents.lines().map(str::to_string)))
+}
+
+fn non_empty_lines(lines: impl IntoIterator<Item = String>) -> Vec<String> {
+    lines
+        .into_iter()
+        .filter(|line| !line.trim().is_empty())
+        .collect()
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum IndexKind {
+    /// Brute-force search over every embedding.
+    Exact,
+    /// Approximate search over an HNSW graph.
+    Hnsw,
+}
+
+#[derive(Args)]
+struct HnswArgs {
+    /// HNSW neighbors per node.
+    #[arg(long, default_value_t = HnswConfig::default().m)]
+    m: usize,
+    /// HNSW candidate list size while building.
+    #[arg(long, default_value_t = HnswConfig::default().ef_construction)]
+    ef_construction: usize,
+    /// HNSW candidate list size while searching.
+    #[arg(long, default_value_t = HnswConfig::default().ef_search)]
+    ef_search: usize,
+}
+
+impl HnswArgs {
+    fn config(&self) -> HnswConfig {
+        HnswConfig {
+            m: self.m,
+            ef_construction: self.ef_construction,
+            ef_search: self.ef_search,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// A single JSON document.
+    Json,
+    /// One JSON object per line.
+    Jsonl,
+    /// Plain whitespace-separated text.
+    Text,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BatchOutputFormat {
+    /// One JSON object per line.
+    Jsonl,
+    /// A single JSON array.
+    Json,
+    /// A memory-mapped binary store that `search --store` can load.
+    Store,
+}
+
+impl From<BatchOutputFormat> for BatchFormat {
+    fn from(format: BatchOutputFormat) -> Self {
+        match format {
+            BatchOutputFormat::Jsonl => BatchFormat::Jsonl,
+            BatchOutputFormat::Json => BatchFormat::Json,
+            BatchOutputFormat::Store => BatchFormat::Store,
+        }
+    }
+}
+
+pub async fn run(cli: Cli) -> Result<()> {
+    let base = cli.model.embedding_config();
+    match cli.command {
+        #[cfg(feature = "server")]
+        Command::Serve {
+            addr,
+            max_batch_size,
+            max_wait_ms,
+            workers,
+        } => {
+            let batcher_config = BatcherConfig {
+                max_batch_size,
+                max_wait: Duration::from_millis(max_wait_ms),
+                ..Default::default()
+            };
+            let registry = cli.registry.registry(&base, workers)?;
+            server::serve(registry, addr, batcher_config).await
+        }
+        Command::Bench {
+            workers,
+            batch_size,
+            file,
+            count,
+        } => {
+            let texts = match file {
+                Some(file) => read_text_file(&file)?,
+                None => bench::synthetic_corpus(count),
+            };
+            let registry = cli.registry.registry(&base, 1)?;
+            let config = registry.config(None)?;
+            let results = bench::compare_pool_sizes(config, &workers, &texts, batch_size)?;
+            println!("workers\ttexts\tseconds\ttexts/s");
+            for result in results {
+                println!(
+                    "{}\t{}\t{:.3}\t{:.1}",
+                    result.workers,
+                    result.texts,
+                    result.elapsed.as_secs_f64(),
+                    result.texts_per_second()
+                );
+            }
+            Ok(())
+        }
+        Command::Compare {
+            candidate,
+            candidate_tokenizer,
+            file,
+            count,
+            k,
+            batch_size,
+        } => {
+            let texts = match file {
+                Some(file) => read_text_file(&file)?,
+                None => bench::synthetic_corpus(count),
+            };
+            let registry = cli.registry.registry(&base, 1)?;
+            let reference = registry.config(None)?;
+            let mut candidate = reference.clone().with_model_path(candidate);
+            if let Some(tokenizer) = candidate_tokenizer {
+                candidate = candidate.with_tokenizer_path(tokenizer);
+            }
+            let comparison = compare::compare_models(reference, &candidate, &texts, batch_size, k)?;
+            let agreement = &comparison.agreement;
+            let seconds = |elapsed: Duration| elapsed.as_secs_f64();
+            println!("texts: {}", agreement.texts);
+            println!("mean cosine: {:.6}", agreement.mean_cosine);
+            println!("min cosine: {:.6}", agreement.min_cosine);
+            println!(
+                "top-{} overlap: {:.4}",
+                agreement.k, agreement.top_k_overlap
+            );
+            println!(
+                "reference: {:.3}s ({:.1} texts/s)",
+                seconds(comparison.reference_elapsed),
+                texts.len() as f64 / seconds(comparison.reference_elapsed)
+            );
+            println!(
+                "candidate: {:.3}s ({:.1} texts/s)",
+                seconds(comparison.candidate_elapsed),
+                texts.len() as f64 / seconds(comparison.candidate_elapsed)
+            );
+            Ok(())
+        }
+        command => {
+            let registry = cli.registry.registry(&base, 1)?;
+            let config = registry.config(None)?.clone();
+            run_with_generator(config, &cli.cache, command)
+        }
+    }
+}
+
+fn run_with_generator(config: EmbeddingConfig, cache: &CacheArgs, command: Command) -> Result<()> {
+    let cache = cache.open(&config)?;
+    let mut generator =
+        EmbeddingGenerator::new(config).context("Failed to initialize embedding generator")?;
+    if let Some(cache) = cache {
+        generator = generator.with_cache(cache);
+    }
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    match command {
+        Command::Embed { input, format } => {
+            let texts = input.read_texts()?;
+            let embeddings = generator
+                .generate_embeddings(&texts)
+                .context("Failed to generate embeddings")?;
+            write_embeddings(&mut out, &texts, &embeddings, format)?;
+        }
+        Command::Similarity {
+            query,
+            input,
+            format,
+        } => {
+            let mut texts = input.read_texts()?;
+            let normalized = generator.is_normalized();
+            match query {
+                Some(query) => {
+                    texts.insert(0, query);
+                    let embeddings = generator
+                        .generate_embeddings(&texts)
+                        .context("Failed to generate embeddings")?;
+                    let query = embeddings.row(0).to_vec();
+                    let scores: Vec<f32> = embeddings
+                        .axis_iter(Axis(0))
+                        .skip(1)
+                        .map(|row| similarity(&query, &row.to_vec(), normalized))
+                        .collect();
+                    write_scores(&mut out, &texts[1..], &scores, format)?;
+                }
+                None => {
+                    let embeddings = generator
+                        .generate_embeddings(&texts)
+                        .context("Failed to generate embeddings")?;
+                    let rows: Vec<Vec<f32>> =
+                        embeddings.axis_iter(Axis(0)).map(|r| r.to_vec()).collect();
+                    let matrix: Vec<Vec<f32>> = rows
+                        .iter()
+                        .map(|a| rows.iter().map(|b| similarity(a, b, normalized)).collect())
+                        .collect();
+                    write_matrix(&mut out, &texts, &matrix, format)?;
+                }
+            }
+        }
+        Command::Info => {
+            let config = generator.config().clone();
+            writeln!(out, "model: {}", config.model_path.display())?;
+            writeln!(out, "tokenizer: {}", config.tokenizer_path.display())?;
+            writeln!(out, "inputs:")?;
+            for (input, (_, bound)) in generator.inputs().iter().zip(&generator.signature().inputs)
+            {
+                writeln!(
+                    out,
+                    "  {}: {} <- {:?}",
+                    input.name, input.description, bound
+                )?;
+            }
+            writeln!(out, "outputs:")?;
+            for output in generator.outputs() {
+                let selected = if output.name == generator.signature().output {
+                    " (embeddings)"
+                } else {
+                    ""
+                };
+                writeln!(out, "  {}: {}{}", output.name, output.description, selected)?;
+            }
+            writeln!(out, "dimension: {}", generator.dimension()?)?;
+            writeln!(out, "pooling: {:?}", config.pooling)?;
+            writeln!(out, "normalized: {}", config.normalize)?;
+            if let Some(report) = generator.session_report() {
+                writeln!(out, "{report}")?;
+            }
+        }
+        Command::Chunk {
+            documents,
+            window,
+            overlap,
+            aggregate,
+        } => {
+            let chunker = generator.chunker(ChunkConfig {
+                window: window.unwrap_or(generator.config().max_length),
+                overlap,
+            })?;
+            for path in documents {
+                let text = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                let (chunks, embeddings) = embed_chunks(&mut generator, &chunker, &text)
+                    .with_context(|| format!("Failed to embed {}", path.display()))?;
+                let line = match aggregate {
+                    Some(aggregation) => {
+                        let normalize = generator.is_normalized();
+                        let embedding = chunking::aggregate(
+                            &chunks,
+                            embeddings.view(),
+                            aggregation,
+                            normalize,
+                        )?;
+                        json!({
+                            "document": path,
+                            "chunks": chunks.len(),
+                            "embedding": embedding.to_vec(),
+                        })
+                    }
+                    None => {
+                        let chunks: Vec<_> = chunks
+                            .iter()
+                            .zip(embeddings.axis_iter(Axis(0)))
+                            .map(|(chunk, row)| {
+                                json!({
+                                    "start": chunk.start,
+                                    "end": chunk.end,
+                                    "tokens": chunk.tokens,
+                                    "text": chunk.text,
+                                    "embedding": row.to_vec(),
+                                })
+                            })
+                            .collect();
+                        json!({ "document": path, "chunks": chunks })
+                    }
+                };
+                writeln!(out, "{line}")?;
+            }
+        }
+        Command::Search {
+            corpus,
+            store,
+            query,
+            k,
+            batch_size,
+            index,
+            hnsw,
+            format,
+        } => {
+            let dimension = generator.dimension()?;
+            let mut index: Box<dyn NearestNeighbors> = match index {
+                IndexKind::Exact => Box::new(VectorIndex::new(dimension)),
+                IndexKind::Hnsw => Box::new(HnswIndex::new(dimension, hnsw.config())),
+            };
+            let source = match (corpus, store) {
+                (_, Some(path)) => {
+                    let store = EmbeddingStore::open(&path)?;
+                    let model_id = generator.config().model_id();
+                    if store.model_id() != model_id {
+                        eprintln!(
+                            "warning: {} was written by {}, queries are embedded by {}",
+                            path.display(),
+                            store.model_id(),
+                            model_id
+                        );
+                    }
+                    store.load_into(index.as_mut())?;
+                    path
+                }
+                (Some(path), None) => {
+                    let documents = read_documents(&path)?;
+                    index_documents(index.as_mut(), &mut generator, documents, batch_size)?;
+                    path
+                }
+                (None, None) => unreachable!("clap requires --corpus or --store"),
+            };
+            anyhow::ensure!(!index.is_empty(), "{} has no records", source.display());
+            eprintln!(
+                "indexed {} records of dimension {}",
+                index.len(),
+                index.dimension()
+            );
+            let hits = index.search_text(&mut generator, &query, k)?;
+            write_hits(&mut out, &hits, format)?;
+        }
+        Command::Batch {
+            input,
+            output,
+            chunk_size,
+            format,
+            resume,
+        } => {
+            let job = BatchJob {
+                input,
+                output,
+                chunk_size,
+                format: format.into(),
+                resume,
+            };
+            let summary = job.run(&mut generator, |summary| {
+                eprintln!("embedded {} records", summary.embedded)
+            })?;
+            eprintln!(
+                "embedded {} records, skipped {} already embedded",
+                summary.embedded, summary.skipped
+            );
+        }
+        #[cfg(feature = "server")]
+        Command::Serve { .. } => unreachable!("handled by run"),
+        Command::Bench { .. } | Command::Compare { .. } => unreachable!("handled by run"),
+    }
+    out.flush()?;
+    if let Some(cache) = generator.cache() {
+        let stats = cache.stats();
+        eprintln!(
+            "cache: {} hits, {} misses, {} entries",
+            stats.hits,
+            stats.misses,
+            cache.len()
+        );
+        cache.save()?;
+    }
+    Ok(())
+}
+
+fn write_embeddings(
+    out: &mut impl Write,
+    texts: &[String],
+    embeddings: &Array2<f32>,
+    format: OutputFormat,
+) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            let rows: Vec<Vec<f32>> = embeddings.axis_iter(Axis(0)).map(|r| r.to_vec()).collect();
+            serde_json::to_writer(&mut *out, &rows)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Jsonl => {
+            for (text, row) in texts.iter().zip(embeddings.axis_iter(Axis(0))) {
+                let line = json!({ "text": text, "embedding": row.to_vec() });
+                writeln!(out, "{line}")?;
+            }
+        }
+        OutputFormat::Text => {
+            for row in embeddings.axis_iter(Axis(0)) {
+                let values: Vec<String> = row.iter().map(f32::to_string).collect();
+                writeln!(out, "{}", values.join(" "))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_scores(
+    out: &mut impl Write,
+    texts: &[String],
+    scores: &[f32],
+    format: OutputFormat,
+) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            let results: Vec<_> = texts
+                .iter()
+                .zip(scores)
+                .map(|(text, score)| json!({ "text": text, "score": score }))
+                .collect();
+            serde_json::to_writer(&mut *out, &results)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Jsonl => {
+            for (text, score) in texts.iter().zip(scores) {
+                writeln!(out, "{}", json!({ "text": text, "score": score }))?;
+            }
+        }
+        OutputFormat::Text => {
+            for (text, score) in texts.iter().zip(scores) {
+                writeln!(out, "{score:.6}\t{text}")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_hits(out: &mut impl Write, hits: &[SearchHit], format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer(&mut *out, hits)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Jsonl => {
+            for hit in hits {
+                serde_json::to_writer(&mut *out, hit)?;
+                writeln!(out)?;
+            }
+        }
+        OutputFormat::Text => {
+            for hit in hits {
+                let text = hit.metadata.get("text").and_then(|t| t.as_str());
+                writeln!(out, "{:.6}\t{}\t{}", hit.score, hit.id, text.unwrap_or(""))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_matrix(
+    out: &mut impl Write,
+    texts: &[String],
+    matrix: &[Vec<f32>],
+    format: OutputFormat,
+) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer(&mut *out, &json!({ "texts": texts, "scores": matrix }))?;
+            writeln!(out)?;
+        }
+        OutputFormat::Jsonl => {
+            for (text, scores) in texts.iter().zip(matrix) {
+                writeln!(out, "{}", json!({ "text": text, "scores": scores }))?;
+            }
+        }
+        OutputFormat::Text => {
+            for scores in matrix {
+                let values: Vec<String> = scores.iter().map(|s| format!("{s:.6}")).collect();
+                writeln!(out, "{}", values.join("\t"))?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,166 @@
+//! Agreement between the embeddings of two models, such as an fp32 model and its INT8
+//! quantization.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use ndarray::{Array2, ArrayView2, Axis, concatenate};
+use serde_json::Value;
+
+use crate::config::EmbeddingConfig;
+use crate::generator::EmbeddingGenerator;
+use crate::index::{NearestNeighbors, VectorIndex};
+use crate::similarity::cosine_similarity;
+
+/// How closely a candidate model, such as an INT8 quantized export, reproduces the embeddings
+/// of a reference model on the same texts.
+#[derive(Debug)]
+pub struct Agreement {
+    /// Texts both models embedded.
+    pub texts: usize,
+    /// Mean cosine similarity between the two embeddings of each text.
+    pub mean_cosine: f32,
+    /// Lowest cosine similarity between the two embeddings of any text.
+    pub min_cosine: f32,
+    /// Neighbors compared per text.
+    pub k: usize,
+    /// Mean fraction of each text's `k` nearest neighbors that both models agree on.
+    pub top_k_overlap: f32,
+}
+
+/// A model comparison with the time each model took to embed the corpus.
+#[derive(Debug)]
+pub struct Comparison {
+    /// How closely the embeddings agree.
+    pub agreement: Agreement,
+    /// Time the reference model took to embed the corpus.
+    pub reference_elapsed: Duration,
+    /// Time the candidate model took to embed the corpus.
+    pub candidate_elapsed: Duration,
+}
+
+/// Embeds `texts` with the `reference` and `candidate` models, `batch_size` texts per call,
+/// and measures how well they agree.
+pub fn compare_models(
+    reference: &EmbeddingConfig,
+    candidate: &EmbeddingConfig,
+    texts: &[String],
+    batch_size: usize,
+    k: usize,
+) -> Result<Comparison> {
+    anyhow::ensure!(texts.len() > 1, "need at least two texts to compare");
+    let (reference, reference_elapsed) = embed_timed(reference, texts, batch_size)?;
+    let (candidate, candidate_elapsed) = embed_timed(candidate, texts, batch_size)?;
+    Ok(Comparison {
+        agreement: agreement(reference.view(), candidate.view(), k)?,
+        reference_elapsed,
+        candidate_elapsed,
+    })
+}
+
+fn embed_timed(
+    config: &EmbeddingConfig,
+    texts: &[String],
+    batch_size: usize,
+) -> Result<(Array2<f32>, Duration)> {
+    anyhow::ensure!(batch_size > 0, "batch size must be at least 1");
+    let mut generator = EmbeddingGenerator::new(config.clone())?;
+    // Untimed warm-up, so that session initialization is not counted.
+    generator.generate_embeddings(&texts[..texts.len().min(batch_size)])?;
+
+    let started = Instant::now();
+    let batches = texts
+        .chunks(batch_size)
+        .map(|batch| generator.generate_embeddings(batch))
+        .collect::<Result<Vec<_>, _>>()?;
+    let elapsed = started.elapsed();
+    let views: Vec<_> = batches.iter().map(|b| b.view()).collect();
+    Ok((concatenate(Axis(0), &views)?, elapsed))
+}
+
+/// Compares two `[N, D]` embedding matrices of the same texts.
+pub fn agreement(
+    reference: ArrayView2<f32>,
+    candidate: ArrayView2<f32>,
+    k: usize,
+) -> Result<Agreement> {
+    anyhow::ensure!(
+        reference.dim() == candidate.dim(),
+        "reference embeddings are {:?}, candidate embeddings are {:?}",
+        reference.dim(),
+        candidate.dim()
+    );
+    anyhow::ensure!(reference.nrows() > 1, "need at least two texts to compare");
+    let cosines: Vec<f32> = reference
+        .rows()
+        .into_iter()
+        .zip(candidate.rows())
+        .map(|(a, b)| cosine_similarity(&a.to_vec(), &b.to_vec()))
+        .collect();
+
+    let k = k.min(reference.nrows() - 1);
+    let reference_neighbors = nearest_neighbors(reference, k)?;
+    let candidate_neighbors = nearest_neighbors(candidate, k)?;
+    let overlap: f32 = reference_neighbors
+        .iter()
+        .zip(&candidate_neighbors)
+        .map(|(a, b)| a.intersection(b).count() as f32 / k as f32)
+        .sum();
+
+    let texts = reference.nrows();
+    Ok(Agreement {
+        texts,
+        mean_cosine: cosines.iter().sum::<f32>() / texts as f32,
+        min_cosine: cosines.iter().copied().fold(f32::INFINITY, f32::min),
+        k,
+        top_k_overlap: overlap / texts as f32,
+    })
+}
+
+/// The `k` nearest other rows of each row of `embeddings`.
+fn nearest_neighbors(embeddings: ArrayView2<f32>, k: usize) -> Result<Vec<HashSet<String>>> {
+    let mut index = VectorIndex::new(embeddings.ncols());
+    let ids: Vec<String> = (0..embeddings.nrows()).map(|i| i.to_string()).collect();
+    index.insert_batch(ids.clone(), embeddings, vec![Value::Null; ids.len()])?;
+    embeddings
+        .rows()
+        .into_iter()
+        .zip(&ids)
+        .map(|(row, id)| {
+            let hits = index.search(&row.to_vec(), k + 1)?;
+            Ok(hits
+                .into_iter()
+                .map(|hit| hit.id)
+                .filter(|hit| hit != id)
+                .take(k)
+                .collect())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+
+    #[test]
+    fn identical_embeddings_agree_fully() {
+        let embeddings = array![[1.0, 0.0], [0.9, 0.1], [0.0, 1.0], [0.1, 0.9]];
+        let agreement = agreement(embeddings.view(), embeddings.view(), 1).unwrap();
+
+        assert!((agreement.mean_cosine - 1.0).abs() < 1e-6);
+        assert_eq!(agreement.top_k_overlap, 1.0);
+    }
+
+    #[test]
+    fn swapped_neighbors_lower_overlap() {
+        let reference = array![[1.0, 0.0], [0.9, 0.1], [0.0, 1.0], [0.1, 0.9]];
+        let candidate = array![[1.0, 0.0], [0.1, 0.9], [0.0, 1.0], [0.9, 0.1]];
+        let agreement = agreement(reference.view(), candidate.view(), 1).unwrap();
+
+        assert_eq!(agreement.top_k_overlap, 0.0);
+        assert!(agreement.min_cosine < 0.5);
+    }
+}
@@ -0,0 +1,278 @@
+//! Settings for loading a model and turning its outputs into embeddings.
+
+use std::path::PathBuf;
+
+use crate::pooling::Pooling;
+
+/// Inference engine that runs the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    /// ONNX Runtime, with the session options of [`EmbeddingConfig`]. Needs the `ort`
+    /// feature.
+    Ort,
+    /// tract, a pure-Rust engine. Session options do not apply. Needs the `tract` feature.
+    Tract,
+}
+
+impl Default for Runtime {
+    /// ONNX Runtime, unless the crate is built with tract only.
+    fn default() -> Self {
+        if cfg!(all(feature = "tract", not(feature = "ort"))) {
+            Runtime::Tract
+        } else {
+            Runtime::Ort
+        }
+    }
+}
+
+impl std::str::FromStr for Runtime {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ort" | "onnxruntime" => Ok(Runtime::Ort),
+            "tract" => Ok(Runtime::Tract),
+            other => anyhow::bail!("unknown runtime: {other} (expected ort or tract)"),
+        }
+    }
+}
+
+/// Graph optimization level applied when the ONNX session is built.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OptimizationLevel {
+    /// No graph optimizations.
+    Disable,
+    /// Basic optimizations such as constant folding and redundant node elimination.
+    #[default]
+    Level1,
+    /// Also fuses common node patterns into single operators.
+    Level2,
+    /// All optimizations, including layout transformations.
+    Level3,
+}
+
+impl std::str::FromStr for OptimizationLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "disable" | "0" => Ok(OptimizationLevel::Disable),
+            "level1" | "1" => Ok(OptimizationLevel::Level1),
+            "level2" | "2" => Ok(OptimizationLevel::Level2),
+            "level3" | "3" => Ok(OptimizationLevel::Level3),
+            other => anyhow::bail!(
+                "unknown optimization level: {other} (expected disable, level1, level2 or level3)"
+            ),
+        }
+    }
+}
+
+/// How a batch of encodings is padded to a common length.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Padding {
+    /// Pad to the longest encoding in the batch.
+    #[default]
+    BatchLongest,
+    /// Pad every encoding to exactly this many tokens.
+    Fixed(usize),
+}
+
+impl std::str::FromStr for Padding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "longest" | "batch-longest" => Ok(Padding::BatchLongest),
+            other => match other.parse::<usize>() {
+                Ok(length) => Ok(Padding::Fixed(length)),
+                Err(_) => anyhow::bail!(
+                    "unknown padding: {other} (expected longest or a fixed token count)"
+                ),
+            },
+        }
+    }
+}
+
+/// Everything needed to load a sentence-transformer ONNX export.
+///
+/// The defaults match the all-MiniLM-L6-v2 files in the working directory.
+#[derive(Debug, Clone)]
+pub struct EmbeddingConfig {
+    /// The ONNX model file.
+    pub model_path: PathBuf,
+    /// The `tokenizer.json` that was exported with the model.
+    pub tokenizer_path: PathBuf,
+    /// Engine that runs the model.
+    pub runtime: Runtime,
+    /// Graph optimizations applied by ONNX Runtime.
+    pub optimization_level: OptimizationLevel,
+    /// Threads used within a single operator.
+    pub intra_threads: usize,
+    /// Threads used to run independent operators concurrently.
+    pub inter_threads: usize,
+    /// Reuse the memory allocation plan of the previous run when input shapes repeat.
+    pub memory_pattern: bool,
+    /// Serve CPU allocations from an arena rather than the system allocator.
+    pub cpu_arena: bool,
+    /// Directory where optimized graphs are saved and reused across process starts.
+    pub optimized_model_dir: Option<PathBuf>,
+    /// How token embeddings are reduced to one embedding per text.
+    pub pooling: Pooling,
+    /// Scale embeddings to unit length.
+    pub normalize: bool,
+    /// How the texts of a batch are padded to a common length.
+    pub padding: Padding,
+    /// Encodings longer than this many tokens, special tokens included, are truncated. Fixed
+    /// padding to fewer tokens lowers the limit to the padded length.
+    pub max_length: usize,
+    /// Truncate texts longer than the limit. When off, such texts fail with
+    /// [`EmbeddingError::InputTooLong`](crate::error::EmbeddingError::InputTooLong).
+    pub truncate: bool,
+    /// Wrap each text in the special tokens of the tokenizer's post-processor, e.g. `[CLS]` and
+    /// `[SEP]` for BERT-style models.
+    pub add_special_tokens: bool,
+    /// Model output to read embeddings from; the first output when `None`.
+    pub output: Option<String>,
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            model_path: PathBuf::from("./model.onnx"),
+            tokenizer_path: PathBuf::from("./tokenizer.json"),
+            runtime: Runtime::default(),
+            optimization_level: OptimizationLevel::default(),
+            intra_threads: 1,
+            inter_threads: 1,
+            memory_pattern: true,
+            cpu_arena: true,
+            optimized_model_dir: None,
+            pooling: Pooling::default(),
+            normalize: false,
+            padding: Padding::default(),
+            // all-MiniLM-L6-v2 was trained on sequences of up to 256 tokens.
+            max_length: 256,
+            truncate: true,
+            add_special_tokens: true,
+            output: None,
+        }
+    }
+}
+
+impl EmbeddingConfig {
+    /// Loads the model from `path`.
+    pub fn with_model_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.model_path = path.into();
+        self
+    }
+
+    /// Loads the tokenizer from `path`.
+    pub fn with_tokenizer_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.tokenizer_path = path.into();
+        self
+    }
+
+    /// Runs the model on `runtime` instead of the default, ONNX Runtime when the `ort`
+    /// feature is enabled.
+    pub fn with_runtime(mut self, runtime: Runtime) -> Self {
+        self.runtime = runtime;
+        self
+    }
+
+    /// Sets the graph optimizations ONNX Runtime applies when loading the model.
+    pub fn with_optimization_level(mut self, level: OptimizationLevel) -> Self {
+        self.optimization_level = level;
+        self
+    }
+
+    /// Sets the threads used to parallelize work within an operator.
+    pub fn with_intra_threads(mut self, threads: usize) -> Self {
+        self.intra_threads = threads;
+        self
+    }
+
+    /// Sets the threads used to run independent operators concurrently. More than one thread
+    /// switches the session to parallel execution.
+    pub fn with_inter_threads(mut self, threads: usize) -> Self {
+        self.inter_threads = threads;
+        self
+    }
+
+    /// Sets whether ONNX Runtime reuses memory allocation plans across runs.
+    pub fn with_memory_pattern(mut self, enable: bool) -> Self {
+        self.memory_pattern = enable;
+        self
+    }
+
+    /// Sets whether CPU allocations are served from an arena.
+    pub fn with_cpu_arena(mut self, enable: bool) -> Self {
+        self.cpu_arena = enable;
+        self
+    }
+
+    /// Saves the optimized graph in `dir` on first load and loads it from there afterwards,
+    /// skipping graph optimization at startup.
+    pub fn with_optimized_model_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.optimized_model_dir = Some(dir.into());
+        self
+    }
+
+    /// Selects how token embeddings are pooled into sentence embeddings.
+    pub fn with_pooling(mut self, pooling: Pooling) -> Self {
+        self.pooling = pooling;
+        self
+    }
+
+    /// Enables L2 normalization of the pooled embeddings, so that the dot product of two
+    /// embeddings equals their cosine similarity.
+    pub fn with_normalization(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Selects how the texts of a batch are padded to a common length.
+    pub fn with_padding(mut self, padding: Padding) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Sets the most tokens a text may have, special tokens included.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Sets whether texts longer than `max_length` are truncated or rejected.
+    pub fn with_truncation(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Sets whether special tokens are added. They are by default, as sentence-transformer
+    /// models are trained with them.
+    pub fn with_special_tokens(mut self, add: bool) -> Self {
+        self.add_special_tokens = add;
+        self
+    }
+
+    /// Reads embeddings from the named model output, such as `sentence_embedding` for exports
+    /// that include the pooling layer, instead of the first output.
+    pub fn with_output(mut self, output: impl Into<String>) -> Self {
+        self.output = Some(output.into());
+        self
+    }
+
+    /// Names the model and pooling that produce the embeddings, for recording next to stored
+    /// vectors. Normalization is left out because the indexes normalize on insert anyway.
+    pub fn model_id(&self) -> String {
+        let name = self
+            .model_path
+            .file_name()
+            .unwrap_or(self.model_path.as_os_str())
+            .to_string_lossy();
+        match &self.output {
+            Some(output) => format!("{name}:{output}:{:?}", self.pooling).to_lowercase(),
+            None => format!("{name}:{:?}", self.pooling).to_lowercase(),
+        }
+    }
+}
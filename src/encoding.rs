@@ -0,0 +1,331 @@
+//! Tokenization of texts into the `[N, L]` tensors a model takes.
+
+use anyhow::Result;
+use ndarray::{Array2, s};
+use tokenizers::{
+    Encoding, PaddingParams, PaddingStrategy, PostProcessor, Tokenizer, TruncationParams,
+    utils::truncation::TruncationStrategy,
+};
+
+use crate::config::{EmbeddingConfig, Padding};
+use crate::error::EmbeddingError;
+
+/// Token ids, attention mask and token type ids for a batch, each of shape `[N, L]`.
+pub struct EncodedBatch {
+    /// Token ids, padded with the tokenizer's pad token.
+    pub ids: Array2<i64>,
+    /// 1 for tokens of the text, 0 for padding.
+    pub attention_mask: Array2<i64>,
+    /// Token type (segment) ids.
+    pub type_ids: Array2<i64>,
+}
+
+impl EncodedBatch {
+    /// Number of non-padding tokens in each row.
+    pub fn token_counts(&self) -> Vec<usize> {
+        self.attention_mask
+            .rows()
+            .into_iter()
+            .map(|row| row.iter().filter(|&&m| m != 0).count())
+            .collect()
+    }
+}
+
+/// Installs the padding and truncation settings from `config` on `tokenizer`, so that every
+/// encoding in a batch comes out with the same length.
+///
+/// Fails if `config` asks for special tokens but the tokenizer has no post-processor to add
+/// them, since the model would then silently see different inputs than it was trained on.
+pub fn configure_tokenizer(
+    tokenizer: &mut Tokenizer,
+    config: &EmbeddingConfig,
+) -> Result<(), EmbeddingError> {
+    let load_error = |e: Box<dyn std::error::Error + Send + Sync>| {
+        EmbeddingError::tokenizer_load(&config.tokenizer_path, e)
+    };
+    if config.add_special_tokens && tokenizer.get_post_processor().is_none() {
+        return Err(load_error(
+            "the tokenizer has no post-processor to add special tokens such as [CLS] and \
+             [SEP]; disable special tokens if the model was trained without them"
+                .into(),
+        ));
+    }
+
+    // Keep the pad token from tokenizer.json if it has one, otherwise fall back to BERT's.
+    let (pad_token, pad_id, pad_type_id) = match tokenizer.get_padding() {
+        Some(params) => (params.pad_token.clone(), params.pad_id, params.pad_type_id),
+        None => {
+            let pad_id = tokenizer.token_to_id("[PAD]").unwrap_or(0);
+            ("[PAD]".to_string(), pad_id, 0)
+        }
+    };
+    let strategy = match config.padding {
+        Padding::BatchLongest => PaddingStrategy::BatchLongest,
+        Padding::Fixed(length) => PaddingStrategy::Fixed(length),
+    };
+    tokenizer.with_padding(Some(PaddingParams {
+        strategy,
+        pad_id,
+        pad_type_id,
+        pad_token,
+        ..Default::default()
+    }));
+    // Without truncation, encode rejects texts over the limit instead.
+    let truncation = config.truncate.then(|| TruncationParams {
+        max_length: token_limit(config),
+        strategy: TruncationStrategy::LongestFirst,
+        ..Default::default()
+    });
+    tokenizer.with_truncation(truncation).map_err(load_error)?;
+    Ok(())
+}
+
+/// Number of special tokens the tokenizer adds around a single text.
+pub fn special_token_count(tokenizer: &Tokenizer, add_special_tokens: bool) -> usize {
+    match tokenizer.get_post_processor() {
+        Some(processor) if add_special_tokens => processor.added_tokens(false),
+        _ => 0,
+    }
+}
+
+/// Tokenizes `texts` into `[N, L]` tensors with a tokenizer set up by [`configure_tokenizer`]
+/// for the same `config`, wrapping each text in the special tokens of the tokenizer's
+/// post-processor when `config.add_special_tokens` is set.
+pub fn encode(
+    tokenizer: &Tokenizer,
+    texts: &[String],
+    config: &EmbeddingConfig,
+) -> Result<EncodedBatch, EmbeddingError> {
+    if texts.is_empty() {
+        return Err(EmbeddingError::EmptyInput);
+    }
+    let encodings = tokenizer
+        .encode_batch(texts.to_vec(), config.add_special_tokens)
+        .map_err(EmbeddingError::tokenize)?;
+
+    let max_length = token_limit(config);
+    let longest = encodings.iter().map(token_count).max().unwrap_or(0);
+    if !config.truncate && longest > max_length {
+        return Err(EmbeddingError::InputTooLong {
+            tokens: longest,
+            max_length,
+        });
+    }
+    let padded_token_length = encodings[0].len();
+    if let Some(e) = encodings.iter().find(|e| e.len() != padded_token_length) {
+        return Err(EmbeddingError::tokenize(format!(
+            "tokenizer produced encodings of length {} and {}; padding is not configured",
+            padded_token_length,
+            e.len()
+        )));
+    }
+
+    let shape = (encodings.len(), padded_token_length);
+    let collect = |field: fn(&Encoding) -> &[u32]| {
+        Array2::from_shape_fn(shape, |(row, column)| field(&encodings[row])[column] as i64)
+    };
+    Ok(EncodedBatch {
+        ids: collect(|e| e.get_ids()),
+        attention_mask: collect(|e| e.get_attention_mask()),
+        type_ids: collect(|e| e.get_type_ids()),
+    })
+}
+
+/// Stacks batches encoded separately by a tokenizer set up by [`configure_tokenizer`] into one,
+/// padding the shorter ones on the right with the tokenizer's pad token.
+pub fn concat_batches(tokenizer: &Tokenizer, batches: &[EncodedBatch]) -> EncodedBatch {
+    let (pad_id, pad_type_id) = tokenizer
+        .get_padding()
+        .map_or((0, 0), |params| (params.pad_id, params.pad_type_id));
+    let rows = batches.iter().map(|batch| batch.ids.nrows()).sum();
+    let length = batches
+        .iter()
+        .map(|batch| batch.ids.ncols())
+        .max()
+        .unwrap_or(0);
+    let stack = |field: fn(&EncodedBatch) -> &Array2<i64>, pad: i64| {
+        let mut stacked = Array2::from_elem((rows, length), pad);
+        let mut offset = 0;
+        for batch in batches {
+            let tensor = field(batch);
+            stacked
+                .slice_mut(s![offset..offset + tensor.nrows(), ..tensor.ncols()])
+                .assign(tensor);
+            offset += tensor.nrows();
+        }
+        stacked
+    };
+    EncodedBatch {
+        ids: stack(|b| &b.ids, pad_id as i64),
+        attention_mask: stack(|b| &b.attention_mask, 0),
+        type_ids: stack(|b| &b.type_ids, pad_type_id as i64),
+    }
+}
+
+/// Most tokens a text may have: `max_length`, or the padded length of fixed padding when that
+/// is shorter, since padding does not shorten longer texts.
+fn token_limit(config: &EmbeddingConfig) -> usize {
+    match config.padding {
+        Padding::Fixed(length) => length.min(config.max_length),
+        Padding::BatchLongest => config.max_length,
+    }
+}
+
+/// Number of non-padding tokens in `encoding`.
+fn token_count(encoding: &Encoding) -> usize {
+    encoding
+        .get_attention_mask()
+        .iter()
+        .filter(|&&m| m != 0)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bert_tokenizer, word_level_tokenizer};
+
+    fn texts(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn mixed_length_batch_is_padded_to_longest() {
+        let mut tokenizer = word_level_tokenizer();
+        let config = EmbeddingConfig::default().with_special_tokens(false);
+        configure_tokenizer(&mut tokenizer, &config).unwrap();
+
+        let batch = encode(
+            &tokenizer,
+            &texts(&["fox", "the fox ran into the jungle", "the fox"]),
+            &config,
+        )
+        .unwrap();
+
+        assert_eq!(batch.ids.dim(), (3, 6));
+        assert_eq!(batch.attention_mask.dim(), (3, 6));
+        assert_eq!(batch.type_ids.dim(), (3, 6));
+        assert_eq!(batch.ids.row(0).to_vec(), vec![5, 0, 0, 0, 0, 0]);
+        assert_eq!(batch.attention_mask.row(0).to_vec(), vec![1, 0, 0, 0, 0, 0]);
+        assert_eq!(batch.attention_mask.row(2).to_vec(), vec![1, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn separately_encoded_batches_concatenate_like_one_batch() {
+        let mut tokenizer = word_level_tokenizer();
+        let config = EmbeddingConfig::default().with_special_tokens(false);
+        configure_tokenizer(&mut tokenizer, &config).unwrap();
+        let all = texts(&["fox", "the fox ran into the jungle", "the fox"]);
+
+        let parts = [
+            encode(&tokenizer, &all[..1], &config).unwrap(),
+            encode(&tokenizer, &all[1..], &config).unwrap(),
+        ];
+        let stacked = concat_batches(&tokenizer, &parts);
+        let whole = encode(&tokenizer, &all, &config).unwrap();
+
+        assert_eq!(stacked.ids, whole.ids);
+        assert_eq!(stacked.attention_mask, whole.attention_mask);
+        assert_eq!(stacked.type_ids, whole.type_ids);
+    }
+
+    #[test]
+    fn fixed_padding_and_truncation() {
+        let mut tokenizer = word_level_tokenizer();
+        let config = EmbeddingConfig::default()
+            .with_padding(Padding::Fixed(4))
+            .with_max_length(4)
+            .with_special_tokens(false);
+        configure_tokenizer(&mut tokenizer, &config).unwrap();
+
+        let batch = encode(
+            &tokenizer,
+            &texts(&["fox", "the fox ran into the jungle"]),
+            &config,
+        )
+        .unwrap();
+
+        assert_eq!(batch.ids.dim(), (2, 4));
+        assert_eq!(batch.ids.row(1).to_vec(), vec![4, 5, 6, 7]);
+        assert_eq!(batch.attention_mask.row(0).to_vec(), vec![1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn fixed_padding_shorter_than_max_length_truncates_to_the_padding() {
+        let mut tokenizer = word_level_tokenizer();
+        let config = EmbeddingConfig::default()
+            .with_padding(Padding::Fixed(4))
+            .with_special_tokens(false);
+        configure_tokenizer(&mut tokenizer, &config).unwrap();
+
+        let batch = encode(
+            &tokenizer,
+            &texts(&["the fox ran into the jungle"]),
+            &config,
+        )
+        .unwrap();
+
+        assert_eq!(batch.ids.row(0).to_vec(), vec![4, 5, 6, 7]);
+        let config = config.with_truncation(false);
+        configure_tokenizer(&mut tokenizer, &config).unwrap();
+        assert!(matches!(
+            encode(
+                &tokenizer,
+                &texts(&["the fox ran into the jungle"]),
+                &config
+            ),
+            Err(EmbeddingError::InputTooLong {
+                tokens: 6,
+                max_length: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn special_tokens_come_from_the_post_processor() {
+        let config = EmbeddingConfig::default().with_max_length(5);
+        assert!(configure_tokenizer(&mut word_level_tokenizer(), &config).is_err());
+
+        let mut tokenizer = bert_tokenizer();
+        configure_tokenizer(&mut tokenizer, &config).unwrap();
+        let batch = encode(
+            &tokenizer,
+            &texts(&["fox", "the fox ran into the jungle"]),
+            &config,
+        )
+        .unwrap();
+
+        assert_eq!(special_token_count(&tokenizer, true), 2);
+        assert_eq!(batch.ids.row(0).to_vec(), vec![2, 5, 3, 0, 0]);
+        // Truncation leaves room for the special tokens.
+        assert_eq!(batch.ids.row(1).to_vec(), vec![2, 4, 5, 6, 3]);
+    }
+
+    #[test]
+    fn rejects_long_and_empty_input_without_truncation() {
+        let mut tokenizer = word_level_tokenizer();
+        let config = EmbeddingConfig::default()
+            .with_max_length(4)
+            .with_truncation(false)
+            .with_special_tokens(false);
+        configure_tokenizer(&mut tokenizer, &config).unwrap();
+
+        let long = encode(
+            &tokenizer,
+            &texts(&["fox", "the fox ran into the jungle"]),
+            &config,
+        );
+        assert!(matches!(
+            long,
+            Err(EmbeddingError::InputTooLong {
+                tokens: 6,
+                max_length: 4
+            })
+        ));
+        assert!(encode(&tokenizer, &texts(&["the fox ran"]), &config).is_ok());
+        assert!(matches!(
+            encode(&tokenizer, &[], &config),
+            Err(EmbeddingError::EmptyInput)
+        ));
+    }
+}
@@ -0,0 +1,130 @@
+//! The typed error returned by the embedding API.
+
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The underlying cause of an [`EmbeddingError`]. Shared, so that one failed batch can be
+/// reported to every request in it.
+pub type Source = Arc<dyn Error + Send + Sync>;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Why embedding failed.
+///
+/// [`EmptyInput`](Self::EmptyInput) and [`InputTooLong`](Self::InputTooLong) are caused by the
+/// texts passed in; the other variants by the model, the tokenizer or the runtime.
+#[derive(Debug, Clone)]
+pub enum EmbeddingError {
+    /// The tokenizer file could not be read, or does not support the configured encoding.
+    TokenizerLoad {
+        /// The tokenizer file.
+        path: PathBuf,
+        /// Why it could not be used.
+        source: Source,
+    },
+    /// The ONNX model could not be loaded, or has no usable inputs and outputs.
+    ModelLoad {
+        /// The model file.
+        path: PathBuf,
+        /// Why it could not be used.
+        source: Source,
+    },
+    /// A text could not be tokenized.
+    Tokenize(Source),
+    /// A text has more tokens than the model accepts and truncation is off.
+    InputTooLong {
+        /// Tokens in the longest text, special tokens included.
+        tokens: usize,
+        /// The most tokens the configuration allows.
+        max_length: usize,
+    },
+    /// The model output does not have the shape of token or sentence embeddings.
+    ShapeMismatch {
+        /// Name of the model output.
+        output: String,
+        /// The shape that was expected.
+        expected: &'static str,
+        /// The shape the output had.
+        actual: Vec<usize>,
+    },
+    /// The runtime failed to run the model.
+    Inference(Source),
+    /// No texts were given.
+    EmptyInput,
+}
+
+impl EmbeddingError {
+    /// Whether the texts passed in, rather than the model or runtime, caused the error.
+    pub fn is_input_error(&self) -> bool {
+        matches!(self, Self::EmptyInput | Self::InputTooLong { .. })
+    }
+
+    pub(crate) fn tokenizer_load(path: impl Into<PathBuf>, source: impl Into<BoxError>) -> Self {
+        Self::TokenizerLoad {
+            path: path.into(),
+            source: source.into().into(),
+        }
+    }
+
+    pub(crate) fn tokenize(source: impl Into<BoxError>) -> Self {
+        Self::Tokenize(source.into().into())
+    }
+
+    pub(crate) fn inference(source: impl Into<BoxError>) -> Self {
+        Self::Inference(source.into().into())
+    }
+
+    pub(crate) fn model_load(path: impl Into<PathBuf>, source: impl Into<BoxError>) -> Self {
+        Self::ModelLoad {
+            path: path.into(),
+            source: source.into().into(),
+        }
+    }
+}
+
+impl fmt::Display for EmbeddingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TokenizerLoad { path, .. } => {
+                write!(f, "Failed to load tokenizer {}", path.display())
+            }
+            Self::ModelLoad { path, .. } => write!(f, "Failed to load model {}", path.display()),
+            Self::Tokenize(_) => write!(f, "Failed to tokenize input"),
+            Self::InputTooLong { tokens, max_length } => write!(
+                f,
+                "input has {tokens} tokens, more than the maximum of {max_length}"
+            ),
+            Self::ShapeMismatch {
+                output,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "output {output} has shape {actual:?}, expected {expected}"
+            ),
+            Self::Inference(_) => write!(f, "Inference failed"),
+            Self::EmptyInput => write!(f, "no texts to embed"),
+        }
+    }
+}
+
+impl Error for EmbeddingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::TokenizerLoad { source, .. }
+            | Self::ModelLoad { source, .. }
+            | Self::Tokenize(source)
+            | Self::Inference(source) => Some(source.as_ref()),
+            Self::InputTooLong { .. } | Self::ShapeMismatch { .. } | Self::EmptyInput => None,
+        }
+    }
+}
+
+#[cfg(feature = "ort")]
+impl From<ort::Error> for EmbeddingError {
+    fn from(e: ort::Error) -> Self {
+        Self::Inference(Arc::new(e))
+    }
+}
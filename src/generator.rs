@@ -0,0 +1,308 @@
+//! [`EmbeddingGenerator`], which turns texts into embeddings.
+
+use anyhow::Result;
+use ndarray::{Array2, ArrayView2, Ix2, Ix3};
+use tokenizers::Tokenizer;
+
+use crate::backend::{Backend, TensorInfo};
+use crate::cache::EmbeddingCache;
+use crate::chunking::{ChunkConfig, Chunker};
+use crate::config::EmbeddingConfig;
+#[cfg(any(feature = "ort", feature = "tract"))]
+use crate::config::Runtime;
+use crate::encoding::{EncodedBatch, concat_batches, configure_tokenizer, encode};
+use crate::error::EmbeddingError;
+use crate::pooling::l2_normalize;
+#[cfg(feature = "ort")]
+use crate::session::OrtBackend;
+use crate::session::SessionReport;
+use crate::signature::{ModelSignature, TokenInput};
+#[cfg(feature = "tract")]
+use crate::tract::TractBackend;
+
+const TOKEN_EMBEDDINGS: &str = "token embeddings [N, L, H]";
+const SENTENCE_EMBEDDINGS: &str = "sentence embeddings [N, H]";
+
+/// Turns texts into sentence embeddings with one tokenizer and one model [`Backend`].
+///
+/// Embedding takes `&mut self`; use a [`GeneratorPool`](crate::pool::GeneratorPool) to embed
+/// from several threads.
+pub struct EmbeddingGenerator {
+    tokenizer: Tokenizer,
+    backend: Box<dyn Backend>,
+    /// Set when the model runs on ONNX Runtime.
+    session_report: Option<SessionReport>,
+    signature: ModelSignature,
+    config: EmbeddingConfig,
+    cache: Option<EmbeddingCache>,
+}
+
+impl EmbeddingGenerator {
+    /// Loads the tokenizer and model named by `config` into the configured runtime and works
+    /// out how to bind the model's inputs and which output holds the embeddings.
+    pub fn new(config: EmbeddingConfig) -> Result<Self, EmbeddingError> {
+        let tokenizer = Tokenizer::from_file(&config.tokenizer_path)
+            .map_err(|e| EmbeddingError::tokenizer_load(&config.tokenizer_path, e))?;
+        let (backend, report) =
+            load_backend(&config).map_err(|e| EmbeddingError::model_load(&config.model_path, e))?;
+        let mut generator = Self::from_boxed(tokenizer, backend, config)?;
+        generator.session_report = report;
+        Ok(generator)
+    }
+
+    /// A generator that runs the model on `backend`, with `tokenizer` set up for `config`.
+    /// The model and tokenizer paths of `config` are not read.
+    pub fn with_backend(
+        tokenizer: Tokenizer,
+        backend: impl Backend + 'static,
+        config: EmbeddingConfig,
+    ) -> Result<Self, EmbeddingError> {
+        Self::from_boxed(tokenizer, Box::new(backend), config)
+    }
+
+    fn from_boxed(
+        mut tokenizer: Tokenizer,
+        backend: Box<dyn Backend>,
+        config: EmbeddingConfig,
+    ) -> Result<Self, EmbeddingError> {
+        configure_tokenizer(&mut tokenizer, &config)?;
+        let input_names: Vec<&str> = backend.inputs().iter().map(|i| i.name.as_str()).collect();
+        let output_names: Vec<&str> = backend.outputs().iter().map(|o| o.name.as_str()).collect();
+        let signature =
+            ModelSignature::discover(&input_names, &output_names, config.output.as_deref())
+                .map_err(|e| EmbeddingError::model_load(&config.model_path, e))?;
+
+        Ok(Self {
+            tokenizer,
+            backend,
+            session_report: None,
+            signature,
+            config,
+            cache: None,
+        })
+    }
+
+    /// Serves repeated texts in [`generate_embeddings`](Self::generate_embeddings) from `cache`
+    /// instead of running the model.
+    pub fn with_cache(mut self, cache: EmbeddingCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// The cache set by [`with_cache`](Self::with_cache).
+    pub fn cache(&self) -> Option<&EmbeddingCache> {
+        self.cache.as_ref()
+    }
+
+    /// The configuration the generator was created with.
+    pub fn config(&self) -> &EmbeddingConfig {
+        &self.config
+    }
+
+    /// The session options applied when the model was loaded into ONNX Runtime.
+    pub fn session_report(&self) -> Option<&SessionReport> {
+        self.session_report.as_ref()
+    }
+
+    /// Whether returned embeddings are unit length.
+    pub fn is_normalized(&self) -> bool {
+        self.config.normalize
+    }
+
+    /// Inputs declared by the model.
+    pub fn inputs(&self) -> &[TensorInfo] {
+        self.backend.inputs()
+    }
+
+    /// Outputs declared by the model.
+    pub fn outputs(&self) -> &[TensorInfo] {
+        self.backend.outputs()
+    }
+
+    /// How tokenizer tensors are bound to the model inputs, and the output embeddings are read
+    /// from.
+    pub fn signature(&self) -> &ModelSignature {
+        &self.signature
+    }
+
+    /// Length of the returned embedding vectors.
+    ///
+    /// Read from the output metadata when the model declares it, otherwise measured by
+    /// embedding a probe text.
+    pub fn dimension(&mut self) -> Result<usize, EmbeddingError> {
+        let declared = self
+            .outputs()
+            .iter()
+            .find(|output| output.name == self.signature.output)
+            .and_then(|output| output.shape.as_ref())
+            .and_then(|shape| shape.last().copied());
+        match declared {
+            Some(dim) if dim > 0 => Ok(dim as usize),
+            _ => Ok(self
+                .generate_embeddings(&["dimension probe".to_string()])?
+                .ncols()),
+        }
+    }
+
+    /// Tokenizes `text` with the generator's padding and truncation settings.
+    pub fn encode(&self, text: &[String]) -> Result<EncodedBatch, EmbeddingError> {
+        encode(&self.tokenizer, text, &self.config)
+    }
+
+    /// Stacks batches from separate [`encode`](Self::encode) calls into one, padded to the
+    /// longest.
+    pub fn concat_encoded(&self, batches: &[EncodedBatch]) -> EncodedBatch {
+        concat_batches(&self.tokenizer, batches)
+    }
+
+    /// A chunker that splits long texts into windows of this generator's tokens.
+    pub fn chunker(&self, config: ChunkConfig) -> Result<Chunker> {
+        Chunker::new(&self.tokenizer, config, self.config.add_special_tokens)
+    }
+
+    /// Embeds `text` and returns a `[N, D]` matrix with one pooled row per input, in input order.
+    ///
+    /// With a cache, only the texts missing from it are run through the model.
+    pub fn generate_embeddings(&mut self, text: &[String]) -> Result<Array2<f32>, EmbeddingError> {
+        let Some(mut cache) = self.cache.take() else {
+            let batch = self.encode(text)?;
+            return self.embed_encoded(&batch);
+        };
+        let result = self.generate_cached(&mut cache, text);
+        self.cache = Some(cache);
+        result
+    }
+
+    fn generate_cached(
+        &mut self,
+        cache: &mut EmbeddingCache,
+        text: &[String],
+    ) -> Result<Array2<f32>, EmbeddingError> {
+        if text.is_empty() {
+            return Err(EmbeddingError::EmptyInput);
+        }
+        let mut rows: Vec<Option<Vec<f32>>> = text
+            .iter()
+            .map(|t| cache.get(t).map(<[f32]>::to_vec))
+            .collect();
+        let missing: Vec<usize> = (0..text.len()).filter(|&i| rows[i].is_none()).collect();
+        if !missing.is_empty() {
+            let texts: Vec<String> = missing.iter().map(|&i| text[i].clone()).collect();
+            let batch = self.encode(&texts)?;
+            let embeddings = self.embed_encoded(&batch)?;
+            for (&i, row) in missing.iter().zip(embeddings.rows()) {
+                cache.insert(&text[i], row.to_vec());
+                rows[i] = Some(row.to_vec());
+            }
+        }
+        let rows: Vec<Vec<f32>> = rows.into_iter().flatten().collect();
+        let dimension = rows[0].len();
+        if let Some(row) = rows.iter().find(|row| row.len() != dimension) {
+            // Only possible when the cache holds embeddings of a different model.
+            return Err(EmbeddingError::ShapeMismatch {
+                output: self.signature.output.clone(),
+                expected: "the dimension of the cached embeddings",
+                actual: vec![row.len()],
+            });
+        }
+        Ok(Array2::from_shape_fn((text.len(), dimension), |(i, j)| {
+            rows[i][j]
+        }))
+    }
+
+    /// Runs the model on an already tokenized batch.
+    ///
+    /// A `[N, L, H]` output holds token embeddings, which are pooled; a `[N, H]` output is
+    /// already a sentence embedding and is used as is.
+    pub fn embed_encoded(&mut self, batch: &EncodedBatch) -> Result<Array2<f32>, EmbeddingError> {
+        let inputs: Vec<(&str, ArrayView2<i64>)> = self
+            .signature
+            .inputs
+            .iter()
+            .map(|(name, input)| {
+                let tensor = match input {
+                    TokenInput::InputIds => &batch.ids,
+                    TokenInput::AttentionMask => &batch.attention_mask,
+                    TokenInput::TokenTypeIds => &batch.type_ids,
+                };
+                (name.as_str(), tensor.view())
+            })
+            .collect();
+
+        let output = self.backend.run(&inputs, &self.signature.output)?;
+        let rows = batch.ids.nrows();
+        let shape_mismatch = |expected| EmbeddingError::ShapeMismatch {
+            output: self.signature.output.clone(),
+            expected,
+            actual: output.shape().to_vec(),
+        };
+        let mut embeddings = match output.shape() {
+            [n, l, _] if *n == rows && *l == batch.ids.ncols() => {
+                let tokens = output
+                    .view()
+                    .into_dimensionality::<Ix3>()
+                    .map_err(|_| shape_mismatch(TOKEN_EMBEDDINGS))?;
+                self.config
+                    .pooling
+                    .apply(tokens, batch.attention_mask.view())
+            }
+            [_, _, _] => return Err(shape_mismatch(TOKEN_EMBEDDINGS)),
+            [n, _] if *n == rows => output
+                .view()
+                .into_dimensionality::<Ix2>()
+                .map_err(|_| shape_mismatch(SENTENCE_EMBEDDINGS))?
+                .to_owned(),
+            _ => return Err(shape_mismatch(SENTENCE_EMBEDDINGS)),
+        };
+        if self.config.normalize {
+            l2_normalize(&mut embeddings);
+        }
+        Ok(embeddings)
+    }
+}
+
+/// Loads `config.model_path` into the runtime named by `config.runtime`. Only ONNX Runtime
+/// reports its session options.
+fn load_backend(config: &EmbeddingConfig) -> Result<(Box<dyn Backend>, Option<SessionReport>)> {
+    match config.runtime {
+        #[cfg(feature = "ort")]
+        Runtime::Ort => {
+            let (backend, report) = OrtBackend::load(config)?;
+            Ok((Box::new(backend), Some(report)))
+        }
+        #[cfg(feature = "tract")]
+        Runtime::Tract => Ok((Box::new(TractBackend::load(&config.model_path)?), None)),
+        #[allow(unreachable_patterns)]
+        runtime => anyhow::bail!("the {runtime:?} runtime is not enabled in this build"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compares against `SentenceTransformer("all-MiniLM-L6-v2").encode(...)`, which adds
+    /// [CLS] and [SEP], mean-pools and normalizes. Needs the model in ./model.onnx and
+    /// ./tokenizer.json; run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn matches_sentence_transformers_reference() {
+        let config = EmbeddingConfig::default().with_normalization(true);
+        let mut generator = EmbeddingGenerator::new(config).unwrap();
+
+        let embeddings = generator
+            .generate_embeddings(&["This is an example sentence".to_string()])
+            .unwrap();
+
+        let reference = [
+            0.0676569, 0.0634960, 0.0487132, 0.0793050, 0.0374481, 0.0026528, 0.0393750, -0.0070984,
+        ];
+        assert_eq!(embeddings.ncols(), 384);
+        for (actual, expected) in embeddings.row(0).iter().zip(reference) {
+            assert!(
+                (actual - expected).abs() < 1e-3,
+                "{actual} differs from the reference {expected}"
+            );
+        }
+    }
+}
@@ -0,0 +1,464 @@
+//! An approximate nearest-neighbor index for corpora too large to scan.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use anyhow::Result;
+use ndarray::ArrayView1;
+use serde_json::Value;
+
+use crate::index::{NearestNeighbors, SearchHit, check_dimension, normalized};
+
+/// Graph parameters of an [`HnswIndex`].
+#[derive(Debug, Clone, Copy)]
+pub struct HnswConfig {
+    /// Neighbors kept per node on the upper layers; layer 0 keeps twice as many.
+    pub m: usize,
+    /// Candidate list size while inserting. Higher builds a better graph, more slowly.
+    pub ef_construction: usize,
+    /// Candidate list size while searching. Higher improves recall at the cost of latency.
+    pub ef_search: usize,
+    /// Seed for the layer assignment, so that builds are reproducible.
+    pub seed: u64,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 200,
+            ef_search: 64,
+            seed: 0x5eed,
+        }
+    }
+}
+
+struct Node {
+    id: String,
+    metadata: Value,
+    /// Neighbor lists, one per layer from 0 up to the node's level.
+    neighbors: Vec<Vec<usize>>,
+    deleted: bool,
+}
+
+/// Approximate nearest-neighbor index over a hierarchical navigable small world graph
+/// (Malkov & Yashunin, 2016), scoring by cosine similarity.
+///
+/// Removing an id leaves its node in the graph as a tombstone so that searches can still route
+/// through it; it is never returned again. Once tombstones outnumber the live nodes, the graph
+/// is rebuilt without them.
+pub struct HnswIndex {
+    config: HnswConfig,
+    dimension: usize,
+    nodes: Vec<Node>,
+    /// Row-major `[nodes, dimension]` matrix of unit-length embeddings.
+    vectors: Vec<f32>,
+    by_id: HashMap<String, usize>,
+    /// Number of tombstoned nodes.
+    deleted: usize,
+    entry_point: Option<usize>,
+    rng: SplitMix64,
+}
+
+/// A node paired with its similarity to the current query, ordered by similarity.
+#[derive(Clone, Copy, PartialEq)]
+struct Scored(f32, usize);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+impl HnswIndex {
+    /// An empty index of `dimension`-long embeddings.
+    pub fn new(dimension: usize, config: HnswConfig) -> Self {
+        Self {
+            dimension,
+            nodes: Vec::new(),
+            vectors: Vec::new(),
+            by_id: HashMap::new(),
+            deleted: 0,
+            entry_point: None,
+            rng: SplitMix64(config.seed),
+            config,
+        }
+    }
+
+    /// Removes `id` from search results. Returns whether it was present.
+    pub fn remove(&mut self, id: &str) -> bool {
+        match self.by_id.remove(id) {
+            Some(node) => {
+                self.nodes[node].deleted = true;
+                self.deleted += 1;
+                if self.deleted > self.by_id.len() {
+                    self.compact();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Rebuilds the graph from the live nodes, so that ids which keep being removed and inserted
+    /// again do not grow the index without bound.
+    fn compact(&mut self) {
+        let nodes = std::mem::take(&mut self.nodes);
+        let vectors = std::mem::take(&mut self.vectors);
+        self.by_id.clear();
+        self.deleted = 0;
+        self.entry_point = None;
+        for (i, node) in nodes.into_iter().enumerate() {
+            if !node.deleted {
+                let vector = &vectors[i * self.dimension..(i + 1) * self.dimension];
+                self.insert(&node.id, vector, node.metadata)
+                    .expect("stored vectors have the index dimension");
+            }
+        }
+    }
+
+    fn vector(&self, node: usize) -> &[f32] {
+        &self.vectors[node * self.dimension..(node + 1) * self.dimension]
+    }
+
+    fn similarity(&self, query: &[f32], node: usize) -> f32 {
+        query
+            .iter()
+            .zip(self.vector(node))
+            .map(|(a, b)| a * b)
+            .sum()
+    }
+
+    fn max_neighbors(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.config.m * 2
+        } else {
+            self.config.m
+        }
+    }
+
+    /// Draws a level from the exponentially decaying distribution with normalization 1/ln(M).
+    fn random_level(&mut self) -> usize {
+        let level_mult = 1.0 / (self.config.m.max(2) as f64).ln();
+        let uniform = self.rng.next_f64().max(f64::MIN_POSITIVE);
+        (-uniform.ln() * level_mult).floor() as usize
+    }
+
+    /// Greedy best-first search of one layer, returning up to `ef` nodes best first.
+    ///
+    /// With `live_only`, tombstoned nodes are still followed but not returned, so they do not
+    /// take up any of the `ef` slots.
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entry_points: &[Scored],
+        ef: usize,
+        layer: usize,
+        live_only: bool,
+    ) -> Vec<Scored> {
+        let returned = |node: usize| !live_only || !self.nodes[node].deleted;
+        let mut visited: HashSet<usize> = entry_points.iter().map(|s| s.1).collect();
+        let mut candidates: BinaryHeap<Scored> = entry_points.iter().copied().collect();
+        let mut results: BinaryHeap<Reverse<Scored>> = entry_points
+            .iter()
+            .copied()
+            .filter(|scored| returned(scored.1))
+            .map(Reverse)
+            .collect();
+
+        while let Some(candidate) = candidates.pop() {
+            let worst = results.peek().map_or(f32::NEG_INFINITY, |r| r.0.0);
+            if candidate.0 < worst && results.len() >= ef {
+                break;
+            }
+            for &neighbor in &self.nodes[candidate.1].neighbors[layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let scored = Scored(self.similarity(query, neighbor), neighbor);
+                let worst = results.peek().map_or(f32::NEG_INFINITY, |r| r.0.0);
+                if results.len() < ef || scored.0 > worst {
+                    candidates.push(scored);
+                    if returned(neighbor) {
+                        results.push(Reverse(scored));
+                        if results.len() > ef {
+                            results.pop();
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut results: Vec<Scored> = results.into_iter().map(|r| r.0).collect();
+        results.sort_by(|a, b| b.cmp(a));
+        results
+    }
+
+    /// Picks up to `max` diverse neighbors from `candidates` (sorted best first): a candidate is
+    /// skipped when it is closer to an already selected neighbor than to the base node, and
+    /// skipped candidates fill any remaining slots.
+    fn select_neighbors(&self, candidates: &[Scored], max: usize) -> Vec<usize> {
+        let mut selected: Vec<usize> = Vec::with_capacity(max);
+        let mut pruned = Vec::new();
+        for &Scored(similarity, candidate) in candidates {
+            if selected.len() >= max {
+                break;
+            }
+            let diverse = selected
+                .iter()
+                .all(|&chosen| self.similarity(self.vector(candidate), chosen) < similarity);
+            if diverse {
+                selected.push(candidate);
+            } else {
+                pruned.push(candidate);
+            }
+        }
+        let room = max - selected.len();
+        selected.extend(pruned.into_iter().take(room));
+        selected
+    }
+
+    /// Adds `node` to the neighbor list of `neighbor` on `layer`, pruning it back to size.
+    fn link(&mut self, neighbor: usize, node: usize, layer: usize) {
+        self.nodes[neighbor].neighbors[layer].push(node);
+        let max = self.max_neighbors(layer);
+        if self.nodes[neighbor].neighbors[layer].len() <= max {
+            return;
+        }
+        let base = self.vector(neighbor).to_vec();
+        let mut candidates: Vec<Scored> = self.nodes[neighbor].neighbors[layer]
+            .iter()
+            .map(|&n| Scored(self.similarity(&base, n), n))
+            .collect();
+        candidates.sort_by(|a, b| b.cmp(a));
+        self.nodes[neighbor].neighbors[layer] = self.select_neighbors(&candidates, max);
+    }
+}
+
+impl NearestNeighbors for HnswIndex {
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn len(&self) -> usize {
+        self.by_id.len()
+    }
+
+    /// Inserting an id that is already present replaces its embedding and metadata.
+    fn insert(&mut self, id: &str, embedding: &[f32], metadata: Value) -> Result<()> {
+        check_dimension(self.dimension, embedding.len())?;
+        self.remove(id);
+
+        let vector = normalized(ArrayView1::from(embedding)).to_vec();
+        let node = self.nodes.len();
+        let level = self.random_level();
+        self.vectors.extend_from_slice(&vector);
+        self.nodes.push(Node {
+            id: id.to_string(),
+            metadata,
+            neighbors: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        self.by_id.insert(id.to_string(), node);
+
+        let Some(entry) = self.entry_point else {
+            self.entry_point = Some(node);
+            return Ok(());
+        };
+        let top = self.nodes[entry].neighbors.len() - 1;
+        let mut entry_points = vec![Scored(self.similarity(&vector, entry), entry)];
+        // Descend greedily through the layers above the new node's level.
+        for layer in (level + 1..=top).rev() {
+            entry_points = self.search_layer(&vector, &entry_points, 1, layer, false);
+        }
+        for layer in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(
+                &vector,
+                &entry_points,
+                self.config.ef_construction,
+                layer,
+                false,
+            );
+            let neighbors = self.select_neighbors(&candidates, self.config.m);
+            for &neighbor in &neighbors {
+                self.link(neighbor, node, layer);
+            }
+            self.nodes[node].neighbors[layer] = neighbors;
+            entry_points = candidates;
+        }
+        if level > top {
+            self.entry_point = Some(node);
+        }
+        Ok(())
+    }
+
+    fn search(&self, query: &[f32], k: usize) -> Result<Vec<SearchHit>> {
+        check_dimension(self.dimension, query.len())?;
+        let Some(entry) = self.entry_point else {
+            return Ok(Vec::new());
+        };
+        let query = normalized(ArrayView1::from(query)).to_vec();
+        let top = self.nodes[entry].neighbors.len() - 1;
+        let mut entry_points = vec![Scored(self.similarity(&query, entry), entry)];
+        for layer in (1..=top).rev() {
+            entry_points = self.search_layer(&query, &entry_points, 1, layer, false);
+        }
+        let ef = self.config.ef_search.max(k);
+        Ok(self
+            .search_layer(&query, &entry_points, ef, 0, true)
+            .into_iter()
+            .take(k)
+            .map(|Scored(score, node)| SearchHit {
+                id: self.nodes[node].id.clone(),
+                score,
+                metadata: self.nodes[node].metadata.clone(),
+            })
+            .collect())
+    }
+}
+
+/// Small deterministic generator for layer assignment.
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::VectorIndex;
+
+    fn random_vectors(rng: &mut SplitMix64, count: usize, dimension: usize) -> Vec<Vec<f32>> {
+        (0..count)
+            .map(|_| {
+                (0..dimension)
+                    .map(|_| rng.next_f64() as f32 * 2.0 - 1.0)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recall_against_exact_search() {
+        let dimension = 32;
+        let mut rng = SplitMix64(7);
+        let corpus = random_vectors(&mut rng, 1000, dimension);
+        let queries = random_vectors(&mut rng, 50, dimension);
+
+        let mut exact = VectorIndex::new(dimension);
+        let mut hnsw = HnswIndex::new(dimension, HnswConfig::default());
+        for (i, vector) in corpus.iter().enumerate() {
+            exact.insert(&i.to_string(), vector, Value::Null).unwrap();
+            hnsw.insert(&i.to_string(), vector, Value::Null).unwrap();
+        }
+
+        let k = 10;
+        let mut found = 0;
+        for query in &queries {
+            let expected: HashSet<String> = exact
+                .search(query, k)
+                .unwrap()
+                .into_iter()
+                .map(|hit| hit.id)
+                .collect();
+            found += hnsw
+                .search(query, k)
+                .unwrap()
+                .iter()
+                .filter(|hit| expected.contains(&hit.id))
+                .count();
+        }
+        let recall = found as f64 / (queries.len() * k) as f64;
+        assert!(recall >= 0.95, "recall@{k} was {recall}");
+    }
+
+    #[test]
+    fn removed_ids_are_not_returned() {
+        let mut rng = SplitMix64(11);
+        let corpus = random_vectors(&mut rng, 200, 8);
+        let mut hnsw = HnswIndex::new(8, HnswConfig::default());
+        for (i, vector) in corpus.iter().enumerate() {
+            hnsw.insert(&i.to_string(), vector, Value::Null).unwrap();
+        }
+
+        let nearest = hnsw.search(&corpus[5], 1).unwrap();
+        assert_eq!(nearest[0].id, "5");
+        assert!(hnsw.remove("5"));
+        assert!(!hnsw.remove("5"));
+        assert_eq!(hnsw.len(), 199);
+        let hits = hnsw.search(&corpus[5], 10).unwrap();
+        assert_eq!(hits.len(), 10);
+        assert!(hits.iter().all(|hit| hit.id != "5"));
+
+        hnsw.insert("5", &corpus[5], Value::from("again")).unwrap();
+        let nearest = hnsw.search(&corpus[5], 1).unwrap();
+        assert_eq!(nearest[0].id, "5");
+        assert_eq!(nearest[0].metadata, Value::from("again"));
+    }
+
+    #[test]
+    fn returns_k_live_hits_after_removing_half_the_ids() {
+        let mut rng = SplitMix64(13);
+        let corpus = random_vectors(&mut rng, 200, 8);
+        let config = HnswConfig {
+            ef_search: 10,
+            ..Default::default()
+        };
+        let mut hnsw = HnswIndex::new(8, config);
+        for (i, vector) in corpus.iter().enumerate() {
+            hnsw.insert(&i.to_string(), vector, Value::Null).unwrap();
+        }
+        for i in (0..200).step_by(2) {
+            assert!(hnsw.remove(&i.to_string()));
+        }
+
+        assert_eq!(hnsw.len(), 100);
+        for query in &corpus[..20] {
+            let hits = hnsw.search(query, 10).unwrap();
+            assert_eq!(hits.len(), 10);
+            assert!(
+                hits.iter()
+                    .all(|hit| hit.id.parse::<usize>().unwrap() % 2 == 1)
+            );
+        }
+    }
+
+    #[test]
+    fn reinserting_ids_does_not_grow_the_graph() {
+        let mut rng = SplitMix64(17);
+        let corpus = random_vectors(&mut rng, 50, 8);
+        let mut hnsw = HnswIndex::new(8, HnswConfig::default());
+        for round in 0..20 {
+            for (i, vector) in corpus.iter().enumerate() {
+                hnsw.insert(&i.to_string(), vector, Value::from(round))
+                    .unwrap();
+            }
+        }
+
+        assert_eq!(hnsw.len(), 50);
+        assert!(hnsw.nodes.len() <= 2 * 50 + 1, "{} nodes", hnsw.nodes.len());
+        let nearest = hnsw.search(&corpus[7], 1).unwrap();
+        assert_eq!(nearest[0].id, "7");
+        assert_eq!(nearest[0].metadata, Value::from(19));
+    }
+}
@@ -0,0 +1,291 @@
+//! Nearest-neighbor search over embeddings, with an exact in-memory index.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use ndarray::{Array1, ArrayView1, ArrayView2, Axis};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::generator::EmbeddingGenerator;
+
+/// A text to index, read from a JSONL record with at least `id` and `text` fields.
+pub struct Document {
+    /// The record's `id`, see [`document_id`].
+    pub id: String,
+    /// The text that is embedded.
+    pub text: String,
+    /// The whole source record.
+    pub metadata: Value,
+}
+
+/// Reads one [`Document`] per non-blank line of a JSONL file.
+pub fn read_documents(path: &Path) -> Result<Vec<Document>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut documents = Vec::new();
+    for (line_number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = || format!("{}:{}: invalid record", path.display(), line_number + 1);
+        let record: Value = serde_json::from_str(&line).with_context(invalid)?;
+        let Some(id) = record.get("id").map(document_id) else {
+            anyhow::bail!("{}: missing \"id\"", invalid());
+        };
+        let Some(text) = record.get("text").and_then(Value::as_str) else {
+            anyhow::bail!("{}: missing \"text\"", invalid());
+        };
+        documents.push(Document {
+            id,
+            text: text.to_string(),
+            metadata: record.clone(),
+        });
+    }
+    Ok(documents)
+}
+
+/// The string form of a record id: strings are used as-is, other values as JSON.
+pub fn document_id(id: &Value) -> String {
+    match id {
+        Value::String(id) => id.clone(),
+        id => id.to_string(),
+    }
+}
+
+/// One result of a nearest-neighbor query.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    /// Id the embedding was inserted under.
+    pub id: String,
+    /// Cosine similarity between the query and the stored embedding.
+    pub score: f32,
+    /// Metadata the embedding was inserted with.
+    pub metadata: Value,
+}
+
+/// An index of embeddings with ids and metadata that answers top-k similarity queries.
+pub trait NearestNeighbors {
+    /// Length of the embeddings the index accepts.
+    fn dimension(&self) -> usize;
+
+    /// Number of searchable embeddings.
+    fn len(&self) -> usize;
+
+    /// Whether the index has no searchable embeddings.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds `embedding` under `id` with `metadata`. Inserting an id that is already present
+    /// replaces its embedding and metadata.
+    fn insert(&mut self, id: &str, embedding: &[f32], metadata: Value) -> Result<()>;
+
+    /// The `k` stored embeddings most similar to `query`, best first.
+    fn search(&self, query: &[f32], k: usize) -> Result<Vec<SearchHit>>;
+
+    /// Inserts each row of the `[N, D]` matrix `embeddings` under the matching id.
+    fn insert_batch(
+        &mut self,
+        ids: Vec<String>,
+        embeddings: ArrayView2<f32>,
+        metadata: Vec<Value>,
+    ) -> Result<()> {
+        anyhow::ensure!(
+            ids.len() == embeddings.nrows() && metadata.len() == embeddings.nrows(),
+            "got {} ids and {} metadata values for {} embeddings",
+            ids.len(),
+            metadata.len(),
+            embeddings.nrows()
+        );
+        check_dimension(self.dimension(), embeddings.ncols())?;
+        for ((id, row), metadata) in ids
+            .into_iter()
+            .zip(embeddings.axis_iter(Axis(0)))
+            .zip(metadata)
+        {
+            self.insert(&id, &row.to_vec(), metadata)?;
+        }
+        Ok(())
+    }
+
+    /// Embeds `text` with `generator` and searches for it.
+    fn search_text(
+        &self,
+        generator: &mut EmbeddingGenerator,
+        text: &str,
+        k: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let query = generator.generate_embeddings(&[text.to_string()])?;
+        self.search(&query.row(0).to_vec(), k)
+    }
+}
+
+/// Embeds `documents` with `generator`, `batch_size` texts per model call, and adds them to
+/// `index`.
+pub fn index_documents(
+    index: &mut dyn NearestNeighbors,
+    generator: &mut EmbeddingGenerator,
+    documents: Vec<Document>,
+    batch_size: usize,
+) -> Result<()> {
+    anyhow::ensure!(batch_size > 0, "batch size must be at least 1");
+    let mut documents = documents.into_iter().peekable();
+    while documents.peek().is_some() {
+        let chunk: Vec<Document> = documents.by_ref().take(batch_size).collect();
+        let texts: Vec<String> = chunk.iter().map(|d| d.text.clone()).collect();
+        let embeddings = generator.generate_embeddings(&texts)?;
+        let (ids, metadata) = chunk.into_iter().map(|d| (d.id, d.metadata)).unzip();
+        index.insert_batch(ids, embeddings.view(), metadata)?;
+    }
+    Ok(())
+}
+
+/// Embeddings with ids and metadata, searched exactly by brute force.
+///
+/// Rows are stored L2-normalized, so a query is scored against every row with one
+/// matrix-vector product.
+pub struct VectorIndex {
+    dimension: usize,
+    ids: Vec<String>,
+    /// Row of each id.
+    rows: HashMap<String, usize>,
+    metadata: Vec<Value>,
+    /// Row-major `[len, dimension]` matrix of unit-length embeddings.
+    vectors: Vec<f32>,
+}
+
+impl VectorIndex {
+    /// An empty index of `dimension`-long embeddings.
+    pub fn new(dimension: usize) -> Self {
+        Self {
+            dimension,
+            ids: Vec::new(),
+            rows: HashMap::new(),
+            metadata: Vec::new(),
+            vectors: Vec::new(),
+        }
+    }
+}
+
+impl NearestNeighbors for VectorIndex {
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn insert(&mut self, id: &str, embedding: &[f32], metadata: Value) -> Result<()> {
+        check_dimension(self.dimension, embedding.len())?;
+        let vector = normalized(ArrayView1::from(embedding));
+        if let Some(&row) = self.rows.get(id) {
+            let start = row * self.dimension;
+            for (stored, x) in self.vectors[start..start + self.dimension]
+                .iter_mut()
+                .zip(&vector)
+            {
+                *stored = *x;
+            }
+            self.metadata[row] = metadata;
+            return Ok(());
+        }
+        self.vectors.extend(vector.iter());
+        self.rows.insert(id.to_string(), self.ids.len());
+        self.ids.push(id.to_string());
+        self.metadata.push(metadata);
+        Ok(())
+    }
+
+    fn search(&self, query: &[f32], k: usize) -> Result<Vec<SearchHit>> {
+        check_dimension(self.dimension, query.len())?;
+        let matrix = ArrayView2::from_shape((self.len(), self.dimension), &self.vectors)?;
+        let scores = matrix.dot(&normalized(ArrayView1::from(query)));
+
+        let mut ranked: Vec<usize> = (0..self.len()).collect();
+        let by_score = |a: &usize, b: &usize| {
+            scores[*b]
+                .partial_cmp(&scores[*a])
+                .unwrap_or(Ordering::Equal)
+        };
+        let k = k.min(ranked.len());
+        if k < ranked.len() {
+            ranked.select_nth_unstable_by(k, by_score);
+            ranked.truncate(k);
+        }
+        ranked.sort_by(by_score);
+        Ok(ranked
+            .into_iter()
+            .map(|i| SearchHit {
+                id: self.ids[i].clone(),
+                score: scores[i],
+                metadata: self.metadata[i].clone(),
+            })
+            .collect())
+    }
+}
+
+pub(crate) fn check_dimension(expected: usize, actual: usize) -> Result<()> {
+    anyhow::ensure!(
+        actual == expected,
+        "embedding has dimension {}, index expects {}",
+        actual,
+        expected
+    );
+    Ok(())
+}
+
+pub(crate) fn normalized(embedding: ArrayView1<f32>) -> Array1<f32> {
+    let norm = embedding.dot(&embedding).sqrt();
+    embedding.mapv(|x| x / norm.max(1e-12))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_returns_top_k_by_cosine_similarity() {
+        let mut index = VectorIndex::new(2);
+        index.insert("east", &[1.0, 0.0], Value::Null).unwrap();
+        index.insert("north", &[0.0, 3.0], Value::Null).unwrap();
+        index
+            .insert("north-east", &[2.0, 2.0], Value::from("diagonal"))
+            .unwrap();
+        index.insert("west", &[-1.0, 0.0], Value::Null).unwrap();
+
+        let hits = index.search(&[1.0, 0.2], 2).unwrap();
+
+        let ids: Vec<&str> = hits.iter().map(|hit| hit.id.as_str()).collect();
+        assert_eq!(ids, ["east", "north-east"]);
+        assert!((hits[0].score - 1.0 / 1.04f32.sqrt()).abs() < 1e-6);
+        assert_eq!(hits[1].metadata, Value::from("diagonal"));
+    }
+
+    #[test]
+    fn inserting_an_existing_id_replaces_it() {
+        let mut index = VectorIndex::new(2);
+        index.insert("a", &[1.0, 0.0], Value::from(1)).unwrap();
+        index.insert("b", &[0.0, 1.0], Value::Null).unwrap();
+        index.insert("a", &[-1.0, 0.0], Value::from(2)).unwrap();
+
+        assert_eq!(index.len(), 2);
+        let hits = index.search(&[-1.0, 0.0], 2).unwrap();
+        assert_eq!(hits[0].id, "a");
+        assert!((hits[0].score - 1.0).abs() < 1e-6);
+        assert_eq!(hits[0].metadata, Value::from(2));
+        assert_eq!(hits[1].id, "b");
+    }
+
+    #[test]
+    fn rejects_mismatched_dimension() {
+        let mut index = VectorIndex::new(3);
+        assert!(index.insert("a", &[1.0, 0.0], Value::Null).is_err());
+        assert!(index.search(&[1.0], 1).is_err());
+    }
+}
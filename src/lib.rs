@@ -0,0 +1,65 @@
+//! Sentence embeddings from ONNX exports of sentence-transformer models.
+//!
+//! [`EmbeddingGenerator`] tokenizes texts, runs the model and pools the token embeddings into
+//! one vector per text, as configured by an [`EmbeddingConfig`]. [`similarity()`] scores the
+//! resulting vectors against each other.
+//!
+//! ```no_run
+//! use onnx_inference::{EmbeddingConfig, EmbeddingGenerator, cosine_similarity};
+//!
+//! # fn example() -> anyhow::Result<()> {
+//! let config = EmbeddingConfig::default()
+//!     .with_model_path("model.onnx")
+//!     .with_tokenizer_path("tokenizer.json");
+//! let mut generator = EmbeddingGenerator::new(config)?;
+//! let texts = ["The fox ran into the jungle", "The fox zoomed out of the forest"];
+//! let embeddings = generator.generate_embeddings(&texts.map(String::from))?;
+//! let score = cosine_similarity(&embeddings.row(0).to_vec(), &embeddings.row(1).to_vec());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The command-line interface and the HTTP server are optional: the `registry` feature adds
+//! named models that load on demand, `server` the OpenAI-compatible HTTP server, and `cli`
+//! the `onnx-inference` binary along with the `batch`, `bench` and `compare` modules
+//! behind its commands. Without them the crate depends on neither tokio nor clap.
+
+#![warn(missing_docs)]
+
+pub mod backend;
+#[cfg(feature = "cli")]
+pub mod batch;
+#[cfg(feature = "registry")]
+pub mod batcher;
+#[cfg(feature = "cli")]
+pub mod bench;
+pub mod cache;
+pub mod chunking;
+#[cfg(feature = "cli")]
+pub mod compare;
+pub mod config;
+pub mod encoding;
+pub mod error;
+pub mod generator;
+pub mod hnsw;
+pub mod index;
+pub mod pool;
+pub mod pooling;
+#[cfg(feature = "registry")]
+pub mod registry;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod session;
+pub mod signature;
+pub mod similarity;
+pub mod store;
+#[cfg(test)]
+mod test_support;
+#[cfg(feature = "tract")]
+pub mod tract;
+
+pub use config::{EmbeddingConfig, OptimizationLevel, Padding, Runtime};
+pub use error::EmbeddingError;
+pub use generator::EmbeddingGenerator;
+pub use pooling::Pooling;
+pub use similarity::{cosine_similarity, dot_product, similarity};
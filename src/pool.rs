@@ -0,0 +1,132 @@
+//! A pool of generators for embedding from several threads.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use anyhow::Result;
+use ndarray::{Array2, Axis, concatenate};
+
+use crate::config::EmbeddingConfig;
+use crate::error::EmbeddingError;
+use crate::generator::EmbeddingGenerator;
+
+/// A fixed set of generators, each with its own ONNX session, so that several batches can run
+/// in parallel. Every session holds its own copy of the model weights.
+pub struct GeneratorPool {
+    idle: Mutex<Vec<EmbeddingGenerator>>,
+    available: Condvar,
+    size: usize,
+}
+
+impl GeneratorPool {
+    /// Loads `workers` generators for `config`.
+    pub fn new(config: &EmbeddingConfig, workers: usize) -> Result<Self> {
+        anyhow::ensure!(workers > 0, "a pool needs at least one worker");
+        let mut generators = Vec::with_capacity(workers);
+        for _ in 0..workers {
+            generators.push(EmbeddingGenerator::new(config.clone())?);
+        }
+        Ok(Self::from_generators(generators))
+    }
+
+    /// A pool of already loaded generators.
+    pub fn from_generators(generators: Vec<EmbeddingGenerator>) -> Self {
+        Self {
+            size: generators.len(),
+            idle: Mutex::new(generators),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Number of generators in the pool.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Runs `f` with exclusive access to an idle generator, waiting for one if all are busy.
+    pub fn with_generator<T>(&self, f: impl FnOnce(&mut EmbeddingGenerator) -> T) -> T {
+        let mut checkout = self.checkout();
+        f(checkout.generator.as_mut().expect("checked out"))
+    }
+
+    fn checkout(&self) -> Checkout<'_> {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        let mut idle = self
+            .available
+            .wait_while(idle, |idle| idle.is_empty())
+            .unwrap_or_else(|e| e.into_inner());
+        Checkout {
+            pool: self,
+            generator: idle.pop(),
+        }
+    }
+
+    /// Embeds `text` on the next idle generator, like
+    /// [`EmbeddingGenerator::generate_embeddings`].
+    pub fn generate_embeddings(&self, text: &[String]) -> Result<Array2<f32>, EmbeddingError> {
+        self.with_generator(|generator| generator.generate_embeddings(text))
+    }
+
+    /// Splits `text` into batches of `batch_size` and embeds them on every generator in
+    /// parallel. Rows come back in input order.
+    pub fn generate_embeddings_parallel(
+        &self,
+        text: &[String],
+        batch_size: usize,
+    ) -> Result<Array2<f32>> {
+        anyhow::ensure!(batch_size > 0, "batch size must be at least 1");
+        let batches: Vec<&[String]> = text.chunks(batch_size).collect();
+        let results: Vec<Mutex<Option<Result<_, EmbeddingError>>>> =
+            batches.iter().map(|_| Mutex::new(None)).collect();
+        let next = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..self.size.min(batches.len()) {
+                scope.spawn(|| {
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(batch) = batches.get(i) else {
+                            break;
+                        };
+                        let result = self.generate_embeddings(batch);
+                        *results[i].lock().unwrap_or_else(|e| e.into_inner()) = Some(result);
+                    }
+                });
+            }
+        });
+
+        let embeddings = results
+            .into_iter()
+            .map(|slot| {
+                slot.into_inner()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .expect("every batch is embedded")
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let views: Vec<_> = embeddings.iter().map(|e| e.view()).collect();
+        if views.is_empty() {
+            return Ok(Array2::zeros((0, 0)));
+        }
+        Ok(concatenate(Axis(0), &views)?)
+    }
+}
+
+/// Returns its generator to the pool when dropped, even if the caller panicked.
+struct Checkout<'a> {
+    pool: &'a GeneratorPool,
+    generator: Option<EmbeddingGenerator>,
+}
+
+impl Drop for Checkout<'_> {
+    fn drop(&mut self) {
+        if let Some(generator) = self.generator.take() {
+            self.pool
+                .idle
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(generator);
+            self.pool.available.notify_one();
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Reducing token embeddings to one sentence embedding.
+
+use ndarray::{Array2, ArrayView2, ArrayView3, Axis};
+use serde::Deserialize;
+
+/// How per-token hidden states are reduced to a single sentence vector.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pooling {
+    /// Average of the token vectors, weighted by the attention mask.
+    #[default]
+    Mean,
+    /// The vector of the first (`[CLS]`) token.
+    Cls,
+    /// Element-wise maximum over the unmasked token vectors.
+    Max,
+}
+
+impl Pooling {
+    /// Pools a `[N, L, D]` hidden state into `[N, D]` using the `[N, L]` attention mask.
+    pub fn apply(self, hidden: ArrayView3<f32>, mask: ArrayView2<i64>) -> Array2<f32> {
+        let (batch, _, dim) = hidden.dim();
+        let mut pooled = Array2::<f32>::zeros((batch, dim));
+        for (i, mut row) in pooled.axis_iter_mut(Axis(0)).enumerate() {
+            let tokens = hidden.index_axis(Axis(0), i);
+            let mask = mask.index_axis(Axis(0), i);
+            match self {
+                Pooling::Cls => {
+                    // An empty sequence has no first token; leave the row zero as the other
+                    // strategies do for a fully masked row.
+                    if let Some(first) = tokens.outer_iter().next() {
+                        row.assign(&first);
+                    }
+                }
+                Pooling::Mean => {
+                    let mut count = 0.0f32;
+                    for (token, &m) in tokens.axis_iter(Axis(0)).zip(mask.iter()) {
+                        if m != 0 {
+                            row += &token;
+                            count += 1.0;
+                        }
+                    }
+                    // Same clamp as sentence-transformers to avoid dividing by zero.
+                    row /= count.max(1e-9);
+                }
+                Pooling::Max => {
+                    row.fill(f32::NEG_INFINITY);
+                    for (token, &m) in tokens.axis_iter(Axis(0)).zip(mask.iter()) {
+                        if m != 0 {
+                            row.zip_mut_with(&token, |acc, &x| *acc = acc.max(x));
+                        }
+                    }
+                    // A fully masked row has nothing to take the maximum of.
+                    row.mapv_inplace(|x| if x.is_finite() { x } else { 0.0 });
+                }
+            }
+        }
+        pooled
+    }
+}
+
+impl std::str::FromStr for Pooling {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mean" => Ok(Pooling::Mean),
+            "cls" => Ok(Pooling::Cls),
+            "max" => Ok(Pooling::Max),
+            other => anyhow::bail!("unknown pooling strategy: {other} (expected mean, cls or max)"),
+        }
+    }
+}
+
+/// Scales every row of `embeddings` to unit L2 norm in place.
+pub fn l2_normalize(embeddings: &mut Array2<f32>) {
+    for mut row in embeddings.axis_iter_mut(Axis(0)) {
+        let norm = row.dot(&row).sqrt();
+        // Same epsilon as torch.nn.functional.normalize, so zero vectors stay zero.
+        row /= norm.max(1e-12);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array3, array};
+
+    use super::*;
+
+    #[test]
+    fn pools_masked_tokens() {
+        let hidden = array![[[1.0, 4.0], [3.0, 2.0], [100.0, 100.0]]];
+        let mask = array![[1, 1, 0]];
+
+        let pool = |pooling: Pooling| pooling.apply(hidden.view(), mask.view());
+
+        assert_eq!(pool(Pooling::Mean), array![[2.0, 3.0]]);
+        assert_eq!(pool(Pooling::Cls), array![[1.0, 4.0]]);
+        assert_eq!(pool(Pooling::Max), array![[3.0, 4.0]]);
+    }
+
+    #[test]
+    fn empty_sequence_pools_to_zero() {
+        let hidden = Array3::<f32>::zeros((2, 0, 3));
+        let mask = Array2::<i64>::zeros((2, 0));
+
+        for pooling in [Pooling::Mean, Pooling::Cls, Pooling::Max] {
+            assert_eq!(
+                pooling.apply(hidden.view(), mask.view()),
+                Array2::<f32>::zeros((2, 3))
+            );
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Named models that are loaded on first use.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::batcher::{Batcher, BatcherConfig};
+use crate::config::EmbeddingConfig;
+use crate::pool::GeneratorPool;
+use crate::pooling::Pooling;
+
+/// One model entry of a registry file. Settings left out fall back to the registry's base
+/// configuration.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelSpec {
+    /// Name callers select the model by.
+    pub name: String,
+    /// Path to the ONNX model, relative to the registry file.
+    pub model: PathBuf,
+    /// Path to the tokenizer.json, relative to the registry file.
+    pub tokenizer: PathBuf,
+    /// See [`EmbeddingConfig::pooling`].
+    pub pooling: Option<Pooling>,
+    /// See [`EmbeddingConfig::normalize`].
+    pub normalize: Option<bool>,
+    /// See [`EmbeddingConfig::max_length`].
+    pub max_length: Option<usize>,
+    /// See [`EmbeddingConfig::output`].
+    pub output: Option<String>,
+    /// Expected embedding length, checked when the model is loaded.
+    pub dimension: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegistryFile {
+    /// Model used when a caller does not name one; the first model when omitted.
+    default: Option<String>,
+    models: Vec<ModelSpec>,
+}
+
+/// A model that has been loaded by a [`ModelRegistry`].
+#[derive(Clone)]
+pub struct LoadedModel {
+    /// Name of the model in the registry.
+    pub name: String,
+    /// The configuration it was loaded with.
+    pub config: EmbeddingConfig,
+    /// Its sessions.
+    pub pool: Arc<GeneratorPool>,
+    /// Batches concurrent requests onto the sessions. Its threads stop once the model is
+    /// unloaded and no caller holds it any more.
+    pub batcher: Batcher,
+}
+
+/// Named embedding models that are loaded on first use and can be unloaded again.
+///
+/// Every model gets its own [`GeneratorPool`] of `workers` sessions, and a [`Batcher`] in
+/// front of it.
+pub struct ModelRegistry {
+    default: String,
+    configs: Vec<(ModelSpec, EmbeddingConfig)>,
+    workers: usize,
+    batcher_config: BatcherConfig,
+    /// One cell per model that has been requested, so that loading one model does not hold up
+    /// callers of the others.
+    loaded: Mutex<HashMap<String, Arc<OnceCell<LoadedModel>>>>,
+}
+
+impl ModelRegistry {
+    /// Reads a JSON registry file of the form
+    /// `{"default": "minilm", "models": [{"name": "minilm", "model": "...", "tokenizer": "..."}]}`.
+    ///
+    /// Thread counts, optimization level and padding come from `base`.
+    pub fn from_file(path: &Path, base: &EmbeddingConfig, workers: usize) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let file: RegistryFile = serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid model registry {}", path.display()))?;
+        let root = path.parent().unwrap_or(Path::new(""));
+        let configs = file
+            .models
+            .into_iter()
+            .map(|spec| {
+                let config = spec_config(&spec, root, base);
+                (spec, config)
+            })
+            .collect();
+        Self::new(file.default, configs, workers)
+    }
+
+    /// A registry holding only `config`, under `name`.
+    pub fn single(name: &str, config: EmbeddingConfig, workers: usize) -> Result<Self> {
+        let spec = ModelSpec {
+            name: name.to_string(),
+            model: config.model_path.clone(),
+            tokenizer: config.tokenizer_path.clone(),
+            pooling: Some(config.pooling),
+            normalize: Some(config.normalize),
+            max_length: Some(config.max_length),
+            output: config.output.clone(),
+            dimension: None,
+        };
+        Self::new(None, vec![(spec, config)], workers)
+    }
+
+    fn new(
+        default: Option<String>,
+        configs: Vec<(ModelSpec, EmbeddingConfig)>,
+        workers: usize,
+    ) -> Result<Self> {
+        anyhow::ensure!(workers > 0, "a pool needs at least one worker");
+        let Some((first, _)) = configs.first() else {
+            anyhow::bail!("model registry has no models");
+        };
+        let default = default.unwrap_or_else(|| first.name.clone());
+        for (i, (spec, _)) in configs.iter().enumerate() {
+            anyhow::ensure!(
+                !configs[..i]
+                    .iter()
+                    .any(|(other, _)| other.name == spec.name),
+                "model {:?} is registered twice",
+                spec.name
+            );
+        }
+        let registry = Self {
+            default,
+            configs,
+            workers,
+            batcher_config: BatcherConfig::default(),
+            loaded: Mutex::new(HashMap::new()),
+        };
+        registry.config(None)?;
+        Ok(registry)
+    }
+
+    /// Registered model names, in file order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.configs.iter().map(|(spec, _)| spec.name.as_str())
+    }
+
+    /// Makes `name` the model used when callers do not name one.
+    pub fn with_default(mut self, name: &str) -> Result<Self> {
+        self.entry(Some(name))?;
+        self.default = name.to_string();
+        Ok(self)
+    }
+
+    /// Batches the requests of every model with `config` instead of the defaults.
+    pub fn with_batcher_config(mut self, config: BatcherConfig) -> Self {
+        self.batcher_config = config;
+        self
+    }
+
+    /// Name of the model used when callers do not name one.
+    pub fn default_name(&self) -> &str {
+        &self.default
+    }
+
+    /// The configuration of model `name`, or of the default model when `None`.
+    pub fn config(&self, name: Option<&str>) -> Result<&EmbeddingConfig> {
+        Ok(&self.entry(name)?.1)
+    }
+
+    fn entry(&self, name: Option<&str>) -> Result<&(ModelSpec, EmbeddingConfig)> {
+        let name = name.unwrap_or(&self.default);
+        self.configs
+            .iter()
+            .find(|(spec, _)| spec.name == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = self.names().collect();
+                anyhow::anyhow!(
+                    "unknown model {name:?}; registered models are {}",
+                    names.join(", ")
+                )
+            })
+    }
+
+    /// Returns model `name` (or the default model), loading it first if necessary.
+    ///
+    /// Concurrent callers of a model that is still loading wait for that load rather than
+    /// starting another. A failed load is retried by the next caller.
+    pub async fn load(&self, name: Option<&str>) -> Result<LoadedModel> {
+        let (spec, config) = self.entry(name)?;
+        let cell = self
+            .loaded
+            .lock()
+            .await
+            .entry(spec.name.clone())
+            .or_default()
+            .clone();
+        let model = cell
+            .get_or_try_init(|| self.load_model(spec, config))
+            .await?;
+        Ok(model.clone())
+    }
+
+    /// Builds the sessions for `spec` on a blocking thread, since that can take seconds and
+    /// would otherwise stall the async runtime.
+    async fn load_model(&self, spec: &ModelSpec, config: &EmbeddingConfig) -> Result<LoadedModel> {
+        let (spec, config) = (spec.clone(), config.clone());
+        let (workers, batcher_config) = (self.workers, self.batcher_config);
+        tokio::task::spawn_blocking(move || {
+            let pool = GeneratorPool::new(&config, workers)
+                .with_context(|| format!("Failed to load model {:?}", spec.name))?;
+            if let Some(expected) = spec.dimension {
+                let actual = pool.with_generator(|generator| generator.dimension())?;
+                anyhow::ensure!(
+                    actual == expected,
+                    "model {:?} produces {}-dimensional embeddings, registry declares {}",
+                    spec.name,
+                    actual,
+                    expected
+                );
+            }
+            let pool = Arc::new(pool);
+            Ok(LoadedModel {
+                name: spec.name,
+                config,
+                batcher: Batcher::spawn(pool.clone(), batcher_config)?,
+                pool,
+            })
+        })
+        .await?
+    }
+
+    /// Whether model `name` has finished loading.
+    pub async fn is_loaded(&self, name: &str) -> bool {
+        let loaded = self.loaded.lock().await;
+        loaded.get(name).is_some_and(|cell| cell.initialized())
+    }
+
+    /// The models that have finished loading, in no particular order.
+    pub async fn loaded(&self) -> Vec<LoadedModel> {
+        let loaded = self.loaded.lock().await;
+        loaded
+            .values()
+            .filter_map(|cell| cell.get().cloned())
+            .collect()
+    }
+
+    /// Drops the registry's sessions for `name`. They are freed once no caller holds the
+    /// model any more. Returns whether the model was loaded.
+    pub async fn unload(&self, name: &str) -> bool {
+        let removed = self.loaded.lock().await.remove(name);
+        removed.is_some_and(|cell| cell.initialized())
+    }
+}
+
+/// Resolves `spec` against the registry file directory and the base configuration.
+fn spec_config(spec: &ModelSpec, root: &Path, base: &EmbeddingConfig) -> EmbeddingConfig {
+    let config = base
+        .clone()
+        .with_model_path(root.join(&spec.model))
+        .with_tokenizer_path(root.join(&spec.tokenizer))
+        .with_pooling(spec.pooling.unwrap_or(base.pooling))
+        .with_normalization(spec.normalize.unwrap_or(base.normalize))
+        .with_max_length(spec.max_length.unwrap_or(base.max_length));
+    match &spec.output {
+        Some(output) => config.with_output(output),
+        None => config,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempPath;
+
+    #[test]
+    fn reads_models_relative_to_the_registry_file() {
+        let dir = TempPath::new("registry");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("models.json");
+        std::fs::write(
+            &path,
+            r#"{
+                "default": "mpnet",
+                "models": [
+                    {"name": "minilm", "model": "minilm/model.onnx", "tokenizer": "minilm/tokenizer.json"},
+                    {"name": "mpnet", "model": "/models/mpnet.onnx", "tokenizer": "/models/mpnet.json",
+                     "pooling": "cls", "normalize": true, "dimension": 768}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let base = EmbeddingConfig::default().with_intra_threads(4);
+        let registry = ModelRegistry::from_file(&path, &base, 1).unwrap();
+
+        assert_eq!(registry.names().collect::<Vec<_>>(), ["minilm", "mpnet"]);
+        let mpnet = registry.config(None).unwrap();
+        assert_eq!(mpnet.model_path, Path::new("/models/mpnet.onnx"));
+        assert_eq!(mpnet.pooling, Pooling::Cls);
+        assert!(mpnet.normalize);
+        let minilm = registry.config(Some("minilm")).unwrap();
+        assert_eq!(minilm.model_path, dir.join("minilm/model.onnx"));
+        assert_eq!(minilm.pooling, Pooling::Mean);
+        assert_eq!(minilm.intra_threads, 4);
+        assert!(registry.config(Some("bert")).is_err());
+    }
+
+    #[tokio::test]
+    async fn failed_loads_are_retried_and_not_counted_as_loaded() {
+        let config = EmbeddingConfig::default()
+            .with_model_path("/missing/model.onnx")
+            .with_tokenizer_path("/missing/tokenizer.json");
+        let registry = ModelRegistry::single("missing", config, 1).unwrap();
+
+        for _ in 0..2 {
+            let error = registry.load(None).await.err().unwrap();
+            assert!(format!("{error:#}").contains("Failed to load model \"missing\""));
+        }
+        assert!(!registry.unload("missing").await);
+    }
+}
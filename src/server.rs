@@ -0,0 +1,274 @@
+//! The HTTP server: an OpenAI-compatible embeddings endpoint, similarity scores and metrics.
+
+use anyhow::Result;
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+};
+use ndarray::Axis;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::batcher::{BatcherConfig, MetricsSnapshot};
+use crate::error::EmbeddingError;
+use crate::registry::{LoadedModel, ModelRegistry};
+use crate::similarity::similarity;
+
+#[derive(Clone)]
+struct AppState {
+    registry: Arc<ModelRegistry>,
+}
+
+impl AppState {
+    /// Model `name`, or the default model, loading it on first use.
+    async fn model(&self, name: Option<&str>) -> Result<LoadedModel, ApiError> {
+        let name = name.unwrap_or(self.registry.default_name());
+        if self.registry.config(Some(name)).is_err() {
+            return Err(ApiError::not_found(format!(
+                "model {name:?} does not exist"
+            )));
+        }
+        Ok(self.registry.load(Some(name)).await?)
+    }
+}
+
+/// `input` of an OpenAI embeddings request: a single string or a list of strings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EmbeddingInput {
+    Single(String),
+    Batch(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct EmbeddingRequest {
+    input: EmbeddingInput,
+    model: Option<String>,
+}
+
+#[derive(Serialize)]
+struct EmbeddingResponse {
+    object: &'static str,
+    data: Vec<EmbeddingData>,
+    model: String,
+    usage: Usage,
+}
+
+#[derive(Serialize)]
+struct EmbeddingData {
+    object: &'static str,
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Serialize)]
+struct Usage {
+    prompt_tokens: usize,
+    total_tokens: usize,
+}
+
+#[derive(Deserialize)]
+struct SimilarityRequest {
+    query: String,
+    texts: Vec<String>,
+    model: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SimilarityResponse {
+    scores: Vec<f32>,
+}
+
+#[derive(Serialize)]
+struct ModelList {
+    object: &'static str,
+    data: Vec<ModelData>,
+}
+
+#[derive(Serialize)]
+struct ModelData {
+    id: String,
+    object: &'static str,
+    loaded: bool,
+}
+
+/// An error response in the OpenAI `{"error": {...}}` shape.
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        let status = match e.downcast_ref::<EmbeddingError>() {
+            Some(e) if e.is_input_error() => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self {
+            status,
+            message: format!("{e:#}"),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let kind = if self.status.is_client_error() {
+            "invalid_request_error"
+        } else {
+            "server_error"
+        };
+        let body = json!({ "error": { "message": self.message, "type": kind } });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// Serves `/v1/embeddings`, `/v1/models`, `/similarity` and `/metrics` on `addr` until the
+/// process is stopped. Models are loaded from `registry` when first requested.
+pub async fn serve(
+    registry: ModelRegistry,
+    addr: SocketAddr,
+    batcher_config: BatcherConfig,
+) -> Result<()> {
+    let state = AppState {
+        registry: Arc::new(registry.with_batcher_config(batcher_config)),
+    };
+    // Load the default model up front so that configuration errors surface at startup.
+    let model = state.registry.load(None).await?;
+    let report = model
+        .pool
+        .with_generator(|generator| generator.session_report().cloned());
+    match report {
+        Some(report) => eprintln!("loaded {}\n{report}", model.name),
+        None => eprintln!("loaded {}", model.name),
+    }
+    let app = Router::new()
+        .route("/v1/embeddings", post(embeddings))
+        .route("/v1/models", get(list_models))
+        .route("/v1/models/{name}", delete(unload_model))
+        .route("/similarity", post(similarity_scores))
+        .route("/metrics", get(metrics))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    eprintln!("listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn embeddings(
+    State(state): State<AppState>,
+    Json(request): Json<EmbeddingRequest>,
+) -> Result<Json<EmbeddingResponse>, ApiError> {
+    let texts = match request.input {
+        EmbeddingInput::Single(text) => vec![text],
+        EmbeddingInput::Batch(texts) => texts,
+    };
+    if texts.is_empty() {
+        return Err(ApiError::bad_request("input must not be empty"));
+    }
+
+    let model = state.model(request.model.as_deref()).await?;
+    let embedded = model.batcher.embed(texts).await?;
+    let data = embedded
+        .embeddings
+        .axis_iter(Axis(0))
+        .enumerate()
+        .map(|(index, row)| EmbeddingData {
+            object: "embedding",
+            index,
+            embedding: row.to_vec(),
+        })
+        .collect();
+    Ok(Json(EmbeddingResponse {
+        object: "list",
+        data,
+        model: model.name,
+        usage: Usage {
+            prompt_tokens: embedded.tokens,
+            total_tokens: embedded.tokens,
+        },
+    }))
+}
+
+async fn similarity_scores(
+    State(state): State<AppState>,
+    Json(request): Json<SimilarityRequest>,
+) -> Result<Json<SimilarityResponse>, ApiError> {
+    if request.texts.is_empty() {
+        return Err(ApiError::bad_request("texts must not be empty"));
+    }
+
+    let model = state.model(request.model.as_deref()).await?;
+    let mut texts = request.texts;
+    texts.insert(0, request.query);
+    let embeddings = model.batcher.embed(texts).await?.embeddings;
+    let query = embeddings.row(0).to_vec();
+    let scores = embeddings
+        .axis_iter(Axis(0))
+        .skip(1)
+        .map(|row| similarity(&query, &row.to_vec(), model.config.normalize))
+        .collect();
+    Ok(Json(SimilarityResponse { scores }))
+}
+
+async fn list_models(State(state): State<AppState>) -> Json<ModelList> {
+    let mut data = Vec::new();
+    for name in state.registry.names() {
+        data.push(ModelData {
+            id: name.to_string(),
+            object: "model",
+            loaded: state.registry.is_loaded(name).await,
+        });
+    }
+    Json(ModelList {
+        object: "list",
+        data,
+    })
+}
+
+/// Unloads a model; it is loaded again by the next request that names it.
+async fn unload_model(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    // The batcher threads, and with them the sessions, stop once in-flight requests finish.
+    if state.registry.unload(&name).await {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::not_found(format!("model {name:?} is not loaded")))
+    }
+}
+
+/// Batching metrics of every loaded model, by model name.
+async fn metrics(State(state): State<AppState>) -> Json<HashMap<String, MetricsSnapshot>> {
+    let loaded = state.registry.loaded().await;
+    Json(
+        loaded
+            .into_iter()
+            .map(|model| (model.name, model.batcher.metrics()))
+            .collect(),
+    )
+}
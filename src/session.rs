@@ -0,0 +1,302 @@
+//! ONNX Runtime sessions. Everything but [`SessionReport`] needs the `ort` feature.
+
+use std::fmt;
+use std::path::PathBuf;
+#[cfg(feature = "ort")]
+use std::{path::Path, time::UNIX_EPOCH};
+
+#[cfg(feature = "ort")]
+use anyhow::{Context, Result};
+#[cfg(feature = "ort")]
+use ndarray::{ArrayD, ArrayView2};
+#[cfg(feature = "ort")]
+use ort::{
+    execution_providers::CPUExecutionProvider,
+    session::{Session, SessionInputValue, builder::GraphOptimizationLevel},
+    value::TensorRef,
+};
+#[cfg(feature = "ort")]
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "ort")]
+use crate::backend::{Backend, TensorInfo};
+#[cfg(feature = "ort")]
+use crate::config::EmbeddingConfig;
+use crate::config::OptimizationLevel;
+#[cfg(feature = "ort")]
+use crate::error::EmbeddingError;
+
+#[cfg(feature = "ort")]
+const EXECUTION_PROVIDER: &str = "CPUExecutionProvider";
+
+/// The session options that were applied when a model was loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionReport {
+    /// The execution provider the session runs on.
+    pub execution_provider: &'static str,
+    /// Graph optimizations applied to the model.
+    pub optimization_level: OptimizationLevel,
+    /// Threads used within a single operator.
+    pub intra_threads: usize,
+    /// Threads used to run independent operators concurrently.
+    pub inter_threads: usize,
+    /// Whether independent operators run in parallel, which is what inter-op threads are for.
+    pub parallel_execution: bool,
+    /// Whether memory allocation plans are reused across runs.
+    pub memory_pattern: bool,
+    /// Whether CPU allocations are served from an arena.
+    pub cpu_arena: bool,
+    /// The saved optimized graph, when one was written or loaded.
+    pub optimized_model: Option<OptimizedModel>,
+}
+
+/// A graph optimized by ONNX Runtime and saved for later runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptimizedModel {
+    /// Where the optimized graph is saved.
+    pub path: PathBuf,
+    /// Whether the graph was loaded from an earlier run rather than written by this one.
+    pub reused: bool,
+}
+
+impl fmt::Display for SessionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+        writeln!(f, "execution provider: {}", self.execution_provider)?;
+        writeln!(f, "optimization level: {:?}", self.optimization_level)?;
+        writeln!(f, "intra-op threads: {}", self.intra_threads)?;
+        if self.parallel_execution {
+            writeln!(
+                f,
+                "execution mode: parallel, {} inter-op threads",
+                self.inter_threads
+            )?;
+        } else {
+            writeln!(f, "execution mode: sequential")?;
+        }
+        writeln!(f, "memory pattern: {}", on_off(self.memory_pattern))?;
+        write!(f, "cpu arena: {}", on_off(self.cpu_arena))?;
+        match &self.optimized_model {
+            Some(model) if model.reused => {
+                write!(f, "\noptimized model: {} (reused)", model.path.display())
+            }
+            Some(model) => write!(f, "\noptimized model: {} (written)", model.path.display()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "ort")]
+impl From<OptimizationLevel> for GraphOptimizationLevel {
+    fn from(level: OptimizationLevel) -> Self {
+        match level {
+            OptimizationLevel::Disable => GraphOptimizationLevel::Disable,
+            OptimizationLevel::Level1 => GraphOptimizationLevel::Level1,
+            OptimizationLevel::Level2 => GraphOptimizationLevel::Level2,
+            OptimizationLevel::Level3 => GraphOptimizationLevel::Level3,
+        }
+    }
+}
+
+/// Builds an ONNX session for `config.model_path` with the session options from `config`.
+///
+/// The CPU execution provider is registered explicitly, so that loading fails rather than
+/// silently running with different options if it cannot be configured.
+///
+/// With `config.optimized_model_dir` set, the optimized graph is saved there on first load and
+/// loaded directly, without optimizing again, by later sessions with the same model, ONNX
+/// Runtime build and optimization level.
+#[cfg(feature = "ort")]
+pub fn build_session(config: &EmbeddingConfig) -> Result<(Session, SessionReport)> {
+    let mut report = SessionReport {
+        execution_provider: EXECUTION_PROVIDER,
+        optimization_level: config.optimization_level,
+        intra_threads: config.intra_threads,
+        inter_threads: config.inter_threads,
+        parallel_execution: config.inter_threads > 1,
+        memory_pattern: config.memory_pattern,
+        cpu_arena: config.cpu_arena,
+        optimized_model: None,
+    };
+    let cpu = CPUExecutionProvider::default()
+        .with_arena_allocator(report.cpu_arena)
+        .build()
+        .error_on_failure();
+    let builder = Session::builder()?
+        .with_execution_providers([cpu])?
+        .with_intra_threads(report.intra_threads)?
+        .with_inter_threads(report.inter_threads)?
+        .with_parallel_execution(report.parallel_execution)?
+        .with_memory_pattern(report.memory_pattern)?;
+
+    let Some(dir) = &config.optimized_model_dir else {
+        let session = builder
+            .with_optimization_level(GraphOptimizationLevel::from(report.optimization_level))?
+            .commit_from_file(&config.model_path)?;
+        return Ok((session, report));
+    };
+
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(optimized_model_name(config, ort::info())?);
+    if path.exists() {
+        let session = builder
+            .with_optimization_level(GraphOptimizationLevel::Disable)?
+            .commit_from_file(&path)
+            .with_context(|| format!("Failed to load optimized model {}", path.display()))?;
+        report.optimized_model = Some(OptimizedModel { path, reused: true });
+        return Ok((session, report));
+    }
+
+    // Written under a temporary name and renamed once complete, so that a concurrent or
+    // interrupted process never loads a partial graph.
+    let partial = path.with_extension(format!("{}.partial", std::process::id()));
+    let session = builder
+        .with_optimization_level(GraphOptimizationLevel::from(report.optimization_level))?
+        .with_optimized_model_path(&partial)?
+        .commit_from_file(&config.model_path)?;
+    std::fs::rename(&partial, &path)
+        .with_context(|| format!("Failed to save optimized model {}", path.display()))?;
+    report.optimized_model = Some(OptimizedModel {
+        path,
+        reused: false,
+    });
+    Ok((session, report))
+}
+
+/// The [`Backend`] that runs models on ONNX Runtime.
+#[cfg(feature = "ort")]
+pub struct OrtBackend {
+    session: Session,
+    inputs: Vec<TensorInfo>,
+    outputs: Vec<TensorInfo>,
+}
+
+#[cfg(feature = "ort")]
+impl OrtBackend {
+    /// Runs models on an already built `session`.
+    pub fn new(session: Session) -> Self {
+        let inputs = session
+            .inputs
+            .iter()
+            .map(|input| TensorInfo {
+                name: input.name.clone(),
+                description: input.input_type.to_string(),
+                shape: input.input_type.tensor_shape().map(|shape| shape.to_vec()),
+            })
+            .collect();
+        let outputs = session
+            .outputs
+            .iter()
+            .map(|output| TensorInfo {
+                name: output.name.clone(),
+                description: output.output_type.to_string(),
+                shape: output
+                    .output_type
+                    .tensor_shape()
+                    .map(|shape| shape.to_vec()),
+            })
+            .collect();
+        Self {
+            session,
+            inputs,
+            outputs,
+        }
+    }
+
+    /// Builds the session for `config` with [`build_session`].
+    pub fn load(config: &EmbeddingConfig) -> Result<(Self, SessionReport)> {
+        let (session, report) = build_session(config)?;
+        Ok((Self::new(session), report))
+    }
+}
+
+#[cfg(feature = "ort")]
+impl Backend for OrtBackend {
+    fn inputs(&self) -> &[TensorInfo] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[TensorInfo] {
+        &self.outputs
+    }
+
+    fn run(
+        &mut self,
+        inputs: &[(&str, ArrayView2<'_, i64>)],
+        output: &str,
+    ) -> Result<ArrayD<f32>, EmbeddingError> {
+        let mut values: Vec<(&str, SessionInputValue)> = Vec::with_capacity(inputs.len());
+        for (name, tensor) in inputs {
+            values.push((name, TensorRef::from_array_view(tensor.view())?.into()));
+        }
+        let outputs = self.session.run(values)?;
+        let Some(value) = outputs.get(output) else {
+            return Err(EmbeddingError::inference(format!(
+                "model has no output {output}"
+            )));
+        };
+        Ok(value.try_extract_array::<f32>()?.to_owned())
+    }
+}
+
+/// File name of the optimized copy of `config.model_path`, keyed by everything the optimized
+/// graph depends on: the source model file, the ONNX Runtime build, the optimization level,
+/// the execution provider and the CPU architecture.
+#[cfg(feature = "ort")]
+fn optimized_model_name(config: &EmbeddingConfig, ort_build: &str) -> Result<String> {
+    let model = &config.model_path;
+    let metadata =
+        std::fs::metadata(model).with_context(|| format!("Failed to read {}", model.display()))?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let key = format!(
+        "{}|{}|{}|{}|{:?}|{}|{}",
+        absolute(model).display(),
+        metadata.len(),
+        modified.as_nanos(),
+        ort_build,
+        config.optimization_level,
+        EXECUTION_PROVIDER,
+        std::env::consts::ARCH,
+    );
+    let digest = Sha256::digest(key.as_bytes());
+    let hash: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+    let stem = model.file_stem().unwrap_or_default().to_string_lossy();
+    Ok(format!("{stem}.{hash}.onnx"))
+}
+
+#[cfg(feature = "ort")]
+fn absolute(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(all(test, feature = "ort"))]
+mod tests {
+    use super::*;
+    use crate::test_support::TempPath;
+
+    #[test]
+    fn optimized_model_name_changes_with_runtime_level_and_model() {
+        let model = TempPath::new("optimized.onnx");
+        std::fs::write(&model, b"graph").unwrap();
+        let config = EmbeddingConfig::default().with_model_path(&*model);
+        let level3 = config
+            .clone()
+            .with_optimization_level(OptimizationLevel::Level3);
+
+        let base = optimized_model_name(&config, "ort 1.22").unwrap();
+        let other_runtime = optimized_model_name(&config, "ort 1.23").unwrap();
+        let other_level = optimized_model_name(&level3, "ort 1.22").unwrap();
+        std::fs::write(&model, b"updated graph").unwrap();
+        let other_model = optimized_model_name(&config, "ort 1.22").unwrap();
+
+        let stem = model.file_stem().unwrap().to_str().unwrap();
+        assert!(base.starts_with(&format!("{stem}.")));
+        assert!(base.ends_with(".onnx"));
+        assert_ne!(base, other_runtime);
+        assert_ne!(base, other_level);
+        assert_ne!(base, other_model);
+    }
+}
@@ -0,0 +1,107 @@
+//! Matching a model's declared inputs and outputs to tokenizer tensors and embeddings.
+
+use anyhow::Result;
+
+/// A tokenizer tensor that can be fed to a model input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenInput {
+    /// The token ids.
+    InputIds,
+    /// 1 for tokens of the text, 0 for padding.
+    AttentionMask,
+    /// The segment each token belongs to; 0 throughout for single texts.
+    TokenTypeIds,
+}
+
+impl TokenInput {
+    /// Recognizes the input names used by Hugging Face and TensorFlow BERT-style exports.
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "input_ids" => Some(TokenInput::InputIds),
+            "attention_mask" | "input_mask" => Some(TokenInput::AttentionMask),
+            "token_type_ids" | "segment_ids" => Some(TokenInput::TokenTypeIds),
+            _ => None,
+        }
+    }
+}
+
+/// Which tokenizer tensor goes to each model input, and which output holds the embeddings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelSignature {
+    /// Model input names, in the order the model declares them.
+    pub inputs: Vec<(String, TokenInput)>,
+    /// Name of the output that holds token or sentence embeddings.
+    pub output: String,
+}
+
+impl ModelSignature {
+    /// Binds the declared inputs by name and selects `output`, or the first output when `None`.
+    ///
+    /// Fails if the model needs an input the tokenizer cannot provide, or has no `input_ids`.
+    pub fn discover(inputs: &[&str], outputs: &[&str], output: Option<&str>) -> Result<Self> {
+        let inputs = inputs
+            .iter()
+            .map(|&name| match TokenInput::from_name(name) {
+                Some(input) => Ok((name.to_string(), input)),
+                None => anyhow::bail!(
+                    "model input {name:?} is not one of input_ids, attention_mask or token_type_ids"
+                ),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        anyhow::ensure!(
+            inputs
+                .iter()
+                .any(|(_, input)| *input == TokenInput::InputIds),
+            "model has no input_ids input"
+        );
+
+        let output = match output {
+            Some(name) => {
+                anyhow::ensure!(
+                    outputs.contains(&name),
+                    "model has no output {name:?}; its outputs are {}",
+                    outputs.join(", ")
+                );
+                name
+            }
+            None => *outputs
+                .first()
+                .ok_or_else(|| anyhow::anyhow!("model has no outputs"))?,
+        };
+        Ok(Self {
+            inputs,
+            output: output.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binds_only_declared_inputs() {
+        let signature = ModelSignature::discover(
+            &["attention_mask", "input_ids"],
+            &["last_hidden_state", "sentence_embedding"],
+            Some("sentence_embedding"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            signature.inputs,
+            [
+                ("attention_mask".to_string(), TokenInput::AttentionMask),
+                ("input_ids".to_string(), TokenInput::InputIds),
+            ]
+        );
+        assert_eq!(signature.output, "sentence_embedding");
+    }
+
+    #[test]
+    fn rejects_unknown_inputs_and_outputs() {
+        assert!(ModelSignature::discover(&["input_ids", "pixel_values"], &["out"], None).is_err());
+        assert!(ModelSignature::discover(&["input_ids"], &["out"], Some("pooled")).is_err());
+        assert!(ModelSignature::discover(&["attention_mask"], &["out"], None).is_err());
+    }
+}
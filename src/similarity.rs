@@ -0,0 +1,26 @@
+//! Similarity scores between embeddings.
+
+/// Dot product of two embeddings of the same length.
+pub fn dot_product(embedding1: &[f32], embedding2: &[f32]) -> f32 {
+    embedding1
+        .iter()
+        .zip(embedding2.iter())
+        .map(|(x, y)| x * y)
+        .sum::<f32>()
+}
+
+/// Cosine of the angle between two embeddings, from -1 to 1.
+pub fn cosine_similarity(embedding1: &[f32], embedding2: &[f32]) -> f32 {
+    let norm1 = embedding1.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm2 = embedding2.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot_product(embedding1, embedding2) / (norm1 * norm2)
+}
+
+/// Cosine similarity, using the plain dot product when both embeddings are already unit length.
+pub fn similarity(embedding1: &[f32], embedding2: &[f32], normalized: bool) -> f32 {
+    if normalized {
+        dot_product(embedding1, embedding2)
+    } else {
+        cosine_similarity(embedding1, embedding2)
+    }
+}
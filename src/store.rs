@@ -0,0 +1,416 @@
+//! Compact binary file of embeddings that can be memory-mapped and searched without re-running
+//! the model.
+//!
+//! Layout, all integers little-endian:
+//!
+//! ```text
+//! 0   magic      b"ONNXEMB\0"
+//! 8   version    u32
+//! 12  dtype      u32   (0 = f32)
+//! 16  dimension  u32
+//! 20  model id   u32 length
+//! 24  count      u64
+//! 32  ids offset u64   byte offset of the id table
+//! 40  model id   UTF-8, zero-padded so the rows start on a 64-byte boundary
+//! ..  rows       count * dimension little-endian f32, contiguous
+//! ..  id table   count * (u32 length + UTF-8 id)
+//! ```
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use memmap2::Mmap;
+use ndarray::{ArrayView2, Axis};
+use serde_json::Value;
+
+use crate::index::NearestNeighbors;
+
+const MAGIC: &[u8; 8] = b"ONNXEMB\0";
+const VERSION: u32 = 1;
+const DTYPE_F32: u32 = 0;
+const HEADER_LEN: usize = 40;
+const ROW_ALIGN: usize = 64;
+
+#[derive(Debug, Clone)]
+struct Header {
+    dimension: usize,
+    model_id: String,
+    count: u64,
+    ids_offset: u64,
+}
+
+impl Header {
+    fn new(model_id: &str, dimension: usize) -> Self {
+        let mut header = Self {
+            dimension,
+            model_id: model_id.to_string(),
+            count: 0,
+            ids_offset: 0,
+        };
+        header.ids_offset = header.rows_offset() as u64;
+        header
+    }
+
+    fn rows_offset(&self) -> usize {
+        (HEADER_LEN + self.model_id.len()).next_multiple_of(ROW_ALIGN)
+    }
+
+    /// End of the rows, or `None` if `count` and `dimension` are too large for a file.
+    fn rows_end(&self) -> Option<usize> {
+        usize::try_from(self.count)
+            .ok()?
+            .checked_mul(self.dimension)?
+            .checked_mul(4)?
+            .checked_add(self.rows_offset())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.rows_offset());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&DTYPE_F32.to_le_bytes());
+        bytes.extend_from_slice(&(self.dimension as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.model_id.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.count.to_le_bytes());
+        bytes.extend_from_slice(&self.ids_offset.to_le_bytes());
+        bytes.extend_from_slice(self.model_id.as_bytes());
+        bytes.resize(self.rows_offset(), 0);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        anyhow::ensure!(
+            bytes.len() >= HEADER_LEN && &bytes[..8] == MAGIC,
+            "not an embedding store"
+        );
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        anyhow::ensure!(
+            u32_at(8) == VERSION,
+            "unsupported store version {}",
+            u32_at(8)
+        );
+        anyhow::ensure!(
+            u32_at(12) == DTYPE_F32,
+            "unsupported store dtype {}",
+            u32_at(12)
+        );
+        anyhow::ensure!(u32_at(16) > 0, "store has dimension 0");
+        let model_id_len = u32_at(20) as usize;
+        let model_id = bytes
+            .get(HEADER_LEN..HEADER_LEN + model_id_len)
+            .context("truncated store header")?;
+        Ok(Self {
+            dimension: u32_at(16) as usize,
+            model_id: String::from_utf8(model_id.to_vec())?,
+            count: u64_at(24),
+            ids_offset: u64_at(32),
+        })
+    }
+}
+
+/// A read-only, memory-mapped embedding store.
+pub struct EmbeddingStore {
+    mmap: Mmap,
+    header: Header,
+    ids: Vec<String>,
+}
+
+impl EmbeddingStore {
+    /// Maps the finished store at `path`.
+    pub fn open(path: &Path) -> Result<Self> {
+        anyhow::ensure!(
+            cfg!(target_endian = "little"),
+            "embedding stores can only be mapped on little-endian targets"
+        );
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        // SAFETY: the map is read-only; the file must not be truncated while the store is open.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let header =
+            Header::decode(&mmap).with_context(|| format!("Failed to read {}", path.display()))?;
+        let rows_end = header
+            .rows_end()
+            .filter(|&end| end as u64 == header.ids_offset && end <= mmap.len());
+        let Some(rows_end) = rows_end else {
+            anyhow::bail!("{} is truncated or was not finished", path.display());
+        };
+        let ids = decode_ids(&mmap[rows_end..], header.count)
+            .with_context(|| format!("Failed to read ids from {}", path.display()))?;
+        Ok(Self { mmap, header, ids })
+    }
+
+    /// Identifies the model that produced the embeddings.
+    pub fn model_id(&self) -> &str {
+        &self.header.model_id
+    }
+
+    /// Length of the stored embeddings.
+    pub fn dimension(&self) -> usize {
+        self.header.dimension
+    }
+
+    /// Number of stored embeddings.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Whether the store holds no embeddings.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Ids of the stored embeddings, in row order.
+    pub fn ids(&self) -> &[String] {
+        &self.ids
+    }
+
+    /// The `[len, dimension]` embedding matrix, read directly from the mapped file.
+    pub fn embeddings(&self) -> ArrayView2<'_, f32> {
+        let start = self.header.rows_offset();
+        let bytes = &self.mmap[start..self.header.ids_offset as usize];
+        // SAFETY: the rows start on a 64-byte boundary of a page-aligned map, so they are
+        // aligned for f32, and any bit pattern is a valid f32.
+        let (prefix, rows, _) = unsafe { bytes.align_to::<f32>() };
+        assert!(prefix.is_empty(), "store rows are not f32-aligned");
+        ArrayView2::from_shape((self.len(), self.dimension()), rows)
+            .expect("row count matches the header")
+    }
+
+    /// Adds every stored embedding to `index`, with null metadata.
+    pub fn load_into(&self, index: &mut dyn NearestNeighbors) -> Result<()> {
+        for (id, row) in self.ids.iter().zip(self.embeddings().axis_iter(Axis(0))) {
+            index.insert(id, &row.to_vec(), Value::Null)?;
+        }
+        Ok(())
+    }
+}
+
+fn decode_ids(mut bytes: &[u8], count: u64) -> Result<Vec<String>> {
+    // Every id takes at least its 4-byte length, which bounds the allocation below.
+    anyhow::ensure!(
+        count <= bytes.len() as u64 / 4,
+        "id table is too short for {count} ids"
+    );
+    let mut ids = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let (len, rest) = bytes
+            .split_first_chunk::<4>()
+            .context("truncated id table")?;
+        let len = u32::from_le_bytes(*len) as usize;
+        let id = rest.get(..len).context("truncated id table")?;
+        ids.push(String::from_utf8(id.to_vec())?);
+        bytes = &rest[len..];
+    }
+    Ok(ids)
+}
+
+/// Writes embeddings to a store file.
+///
+/// Rows go to a `.partial` file next to the store, which [`StoreWriter::finish`] completes with
+/// the id table and header and renames over the store. Until then the store at the path is left
+/// as it was, so an interrupted or failed run never leaves it unreadable.
+pub struct StoreWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    partial: PathBuf,
+    header: Header,
+    ids: Vec<String>,
+}
+
+impl StoreWriter {
+    /// Creates a new store at `path`, replacing any existing file once finished.
+    pub fn create(path: &Path, model_id: &str, dimension: usize) -> Result<Self> {
+        anyhow::ensure!(dimension > 0, "embeddings must have at least one dimension");
+        let partial = partial_path(path);
+        let file = File::create(&partial)
+            .with_context(|| format!("Failed to create {}", partial.display()))?;
+        let header = Header::new(model_id, dimension);
+        let mut file = BufWriter::new(file);
+        file.write_all(&header.encode())?;
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            partial,
+            header,
+            ids: Vec::new(),
+        })
+    }
+
+    /// Opens `path` for appending, creating it if it does not exist. The model id and dimension
+    /// must match the existing store.
+    pub fn append(path: &Path, model_id: &str, dimension: usize) -> Result<Self> {
+        if !path.exists() {
+            return Self::create(path, model_id, dimension);
+        }
+        let EmbeddingStore { header, ids, .. } = EmbeddingStore::open(path)?;
+        anyhow::ensure!(
+            header.model_id == model_id && header.dimension == dimension,
+            "{} holds {}-dimensional embeddings from {}, not {}-dimensional embeddings from {}",
+            path.display(),
+            header.dimension,
+            header.model_id,
+            dimension,
+            model_id
+        );
+        // New rows overwrite the old id table in the copy, which finish() writes out again.
+        let partial = partial_path(path);
+        fs::copy(path, &partial).with_context(|| {
+            format!("Failed to copy {} to {}", path.display(), partial.display())
+        })?;
+        let mut file = OpenOptions::new().write(true).open(&partial)?;
+        file.set_len(header.ids_offset)?;
+        file.seek(SeekFrom::Start(header.ids_offset))?;
+        Ok(Self {
+            file: BufWriter::new(file),
+            path: path.to_path_buf(),
+            partial,
+            header,
+            ids,
+        })
+    }
+
+    /// Writes each row of the `[N, dimension]` matrix `embeddings` under the matching id.
+    pub fn write(&mut self, ids: &[String], embeddings: ArrayView2<f32>) -> Result<()> {
+        anyhow::ensure!(
+            ids.len() == embeddings.nrows(),
+            "got {} ids for {} embeddings",
+            ids.len(),
+            embeddings.nrows()
+        );
+        anyhow::ensure!(
+            embeddings.ncols() == self.header.dimension,
+            "embedding has dimension {}, store expects {}",
+            embeddings.ncols(),
+            self.header.dimension
+        );
+        for row in embeddings.axis_iter(Axis(0)) {
+            for value in row {
+                self.file.write_all(&value.to_le_bytes())?;
+            }
+        }
+        self.ids.extend_from_slice(ids);
+        Ok(())
+    }
+
+    /// Writes the id table and header and replaces the store with the finished file.
+    pub fn finish(mut self) -> Result<()> {
+        for id in &self.ids {
+            self.file.write_all(&(id.len() as u32).to_le_bytes())?;
+            self.file.write_all(id.as_bytes())?;
+        }
+        self.header.count = self.ids.len() as u64;
+        self.header.ids_offset = self.header.rows_end().context("store is too large")? as u64;
+        self.file.flush()?;
+        let file = self.file.get_mut();
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&self.header.encode())?;
+        file.sync_all()?;
+        fs::rename(&self.partial, &self.path).with_context(|| {
+            format!(
+                "Failed to move {} to {}",
+                self.partial.display(),
+                self.path.display()
+            )
+        })
+    }
+}
+
+impl Drop for StoreWriter {
+    /// Discards the rows of a writer that was not finished.
+    fn drop(&mut self) {
+        // After finish() the partial file has already been renamed away.
+        let _ = fs::remove_file(&self.partial);
+    }
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+    use crate::test_support::TempPath;
+
+    #[test]
+    fn append_and_reopen() {
+        let path = TempPath::new("store.bin");
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+
+        let mut writer = StoreWriter::create(&path, "minilm", 3).unwrap();
+        writer
+            .write(
+                &ids(&["a", "b"]),
+                array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]].view(),
+            )
+            .unwrap();
+        writer.finish().unwrap();
+
+        let mut writer = StoreWriter::append(&path, "minilm", 3).unwrap();
+        writer
+            .write(&ids(&["c"]), array![[7.0, 8.0, 9.0]].view())
+            .unwrap();
+        writer.finish().unwrap();
+        assert!(StoreWriter::append(&path, "other-model", 3).is_err());
+
+        let store = EmbeddingStore::open(&path).unwrap();
+        assert_eq!(store.model_id(), "minilm");
+        assert_eq!(store.ids(), ids(&["a", "b", "c"]));
+        assert_eq!(
+            store.embeddings(),
+            array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]
+        );
+    }
+
+    #[test]
+    fn unfinished_append_leaves_the_store_readable() {
+        let path = TempPath::new("store-unfinished.bin");
+        let mut writer = StoreWriter::create(&path, "minilm", 2).unwrap();
+        writer
+            .write(&["a".to_string()], array![[1.0, 2.0]].view())
+            .unwrap();
+        writer.finish().unwrap();
+
+        let mut writer = StoreWriter::append(&path, "minilm", 2).unwrap();
+        writer
+            .write(&["b".to_string()], array![[3.0, 4.0]].view())
+            .unwrap();
+        drop(writer);
+
+        let store = EmbeddingStore::open(&path).unwrap();
+        assert_eq!(store.ids(), ["a"]);
+        assert_eq!(store.embeddings(), array![[1.0, 2.0]]);
+        assert!(!partial_path(&path).exists());
+    }
+
+    #[test]
+    fn rejects_a_header_whose_row_size_overflows() {
+        let path = TempPath::new("store-overflow.bin");
+        let mut header = Header::new("minilm", u32::MAX as usize);
+        header.count = u64::MAX / 4;
+        std::fs::write(&path, header.encode()).unwrap();
+
+        assert!(EmbeddingStore::open(&path).is_err());
+    }
+
+    #[test]
+    fn rejects_a_zero_dimension_or_an_id_count_the_file_cannot_hold() {
+        let path = TempPath::new("store-zero-dim.bin");
+        let mut header = Header::new("m", 0);
+        header.count = u64::MAX / 2;
+        std::fs::write(&path, header.encode()).unwrap();
+
+        assert!(EmbeddingStore::open(&path).is_err());
+        assert!(decode_ids(&[0; 8], 3).is_err());
+        assert_eq!(decode_ids(&[0; 8], 2).unwrap(), ["", ""]);
+    }
+}
@@ -0,0 +1,83 @@
+//! Fixtures shared by the unit tests and, through `#[path]`, the integration tests.
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use tokenizers::Tokenizer;
+use tokenizers::models::wordlevel::WordLevel;
+use tokenizers::pre_tokenizers::whitespace::Whitespace;
+use tokenizers::processors::bert::BertProcessing;
+
+/// Vocabulary of [`word_level_tokenizer`], in id order.
+pub const VOCAB: [&str; 9] = [
+    "[PAD]", "[UNK]", "[CLS]", "[SEP]", "the", "fox", "ran", "into", "jungle",
+];
+
+/// Id of `token` in [`VOCAB`].
+pub fn token_id(token: &str) -> u32 {
+    VOCAB.iter().position(|t| *t == token).unwrap() as u32
+}
+
+/// A tokenizer that splits on whitespace and maps the words of [`VOCAB`] to their positions,
+/// without special tokens.
+pub fn word_level_tokenizer() -> Tokenizer {
+    let vocab: HashMap<String, u32> = VOCAB
+        .iter()
+        .map(|token| (token.to_string(), token_id(token)))
+        .collect();
+    let model = WordLevel::builder()
+        .vocab(vocab)
+        .unk_token("[UNK]".to_string())
+        .build()
+        .unwrap();
+    let mut tokenizer = Tokenizer::new(model);
+    tokenizer.with_pre_tokenizer(Whitespace {});
+    tokenizer
+}
+
+/// [`word_level_tokenizer`] that wraps each text in `[CLS]` and `[SEP]`.
+pub fn bert_tokenizer() -> Tokenizer {
+    let mut tokenizer = word_level_tokenizer();
+    tokenizer.with_post_processor(BertProcessing::new(
+        ("[SEP]".to_string(), token_id("[SEP]")),
+        ("[CLS]".to_string(), token_id("[CLS]")),
+    ));
+    tokenizer
+}
+
+/// A path in the temp directory, unique to the test process, whose file or directory is
+/// deleted on drop, also when the test fails.
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    /// `name`, prefixed with the process id so that concurrent test runs do not collide.
+    pub fn new(name: &str) -> Self {
+        let name = format!("onnx-inference-{}-{name}", std::process::id());
+        Self(std::env::temp_dir().join(name))
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if self.0.is_dir() {
+            let _ = std::fs::remove_dir_all(&self.0);
+        } else {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+}
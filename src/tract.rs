@@ -0,0 +1,161 @@
+//! Models running on tract, a pure-Rust inference engine. Needs the `tract` feature.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use ndarray::{ArrayD, ArrayView2};
+use tract_onnx::prelude::*;
+
+use crate::backend::{Backend, TensorInfo};
+use crate::error::EmbeddingError;
+
+/// The [`Backend`] that runs models on tract, a pure-Rust inference engine, so that builds do
+/// not need the native ONNX Runtime library.
+pub struct TractBackend {
+    plan: TypedRunnableModel<TypedModel>,
+    inputs: Vec<TensorInfo>,
+    outputs: Vec<TensorInfo>,
+}
+
+impl TractBackend {
+    /// Loads and optimizes the ONNX model at `path`.
+    ///
+    /// Every input is declared as an `[N, L]` tensor of token ids, with the batch size and
+    /// sequence length left symbolic so that one plan serves every batch.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut model = tract_onnx::onnx()
+            .model_for_path(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let batch = model.symbols.sym("N");
+        let length = model.symbols.sym("L");
+        for ix in 0..model.inputs.len() {
+            let fact = InferenceFact::dt_shape(i64::datum_type(), [batch.clone(), length.clone()]);
+            model.set_input_fact(ix, fact)?;
+        }
+        let inputs = model
+            .input_outlets()?
+            .iter()
+            .map(|&outlet| TensorInfo {
+                name: model.node(outlet.node).name.clone(),
+                description: "i64[N, L]".to_string(),
+                shape: Some(vec![-1, -1]),
+            })
+            .collect();
+        let output_names: Vec<String> = model
+            .output_outlets()?
+            .iter()
+            .map(|&outlet| match model.outlet_label(outlet) {
+                Some(label) => label.to_string(),
+                None => model.node(outlet.node).name.clone(),
+            })
+            .collect();
+
+        let model = model
+            .into_optimized()
+            .with_context(|| format!("Failed to optimize {}", path.display()))?;
+        let outputs = output_names
+            .into_iter()
+            .enumerate()
+            .map(|(ix, name)| {
+                let fact = model.output_fact(ix)?;
+                let shape = fact
+                    .shape
+                    .iter()
+                    .map(|dim| dim.to_i64().unwrap_or(-1))
+                    .collect();
+                Ok(TensorInfo {
+                    name,
+                    description: format!("{fact:?}"),
+                    shape: Some(shape),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            plan: model.into_runnable()?,
+            inputs,
+            outputs,
+        })
+    }
+}
+
+impl Backend for TractBackend {
+    fn inputs(&self) -> &[TensorInfo] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[TensorInfo] {
+        &self.outputs
+    }
+
+    fn run(
+        &mut self,
+        inputs: &[(&str, ArrayView2<'_, i64>)],
+        output: &str,
+    ) -> Result<ArrayD<f32>, EmbeddingError> {
+        // The plan takes its inputs by position, in the order the model declares them.
+        let mut values = TVec::new();
+        for input in &self.inputs {
+            let Some((_, tensor)) = inputs.iter().find(|(name, _)| *name == input.name) else {
+                return Err(EmbeddingError::inference(format!(
+                    "no tensor bound to input {}",
+                    input.name
+                )));
+            };
+            values.push(Tensor::from(tensor.to_owned()).into_tvalue());
+        }
+        let Some(ix) = self.outputs.iter().position(|o| o.name == output) else {
+            return Err(EmbeddingError::inference(format!(
+                "model has no output {output}"
+            )));
+        };
+
+        let results = self.plan.run(values).map_err(EmbeddingError::inference)?;
+        let embeddings = results[ix]
+            .to_array_view::<f32>()
+            .map_err(EmbeddingError::inference)?;
+        Ok(embeddings.to_owned())
+    }
+}
+
+#[cfg(all(test, feature = "ort"))]
+mod tests {
+    use crate::config::{EmbeddingConfig, Runtime};
+    use crate::generator::EmbeddingGenerator;
+    use crate::similarity::cosine_similarity;
+
+    /// Embeds the same texts on ONNX Runtime and on tract. Needs all-MiniLM-L6-v2 in
+    /// ./model.onnx and ./tokenizer.json; run with `cargo test --features tract -- --ignored`.
+    #[test]
+    #[ignore]
+    fn matches_onnx_runtime_embeddings() {
+        let texts: Vec<String> = [
+            "This is an example sentence",
+            "The fox ran into the jungle",
+            "A considerably longer sentence, so that the batch needs padding and the attention \
+             mask has to be respected by both runtimes",
+        ]
+        .map(String::from)
+        .to_vec();
+        let config = EmbeddingConfig::default().with_normalization(true);
+        let mut ort = EmbeddingGenerator::new(config.clone().with_runtime(Runtime::Ort)).unwrap();
+        let mut tract = EmbeddingGenerator::new(config.with_runtime(Runtime::Tract)).unwrap();
+
+        let expected = ort.generate_embeddings(&texts).unwrap();
+        let actual = tract.generate_embeddings(&texts).unwrap();
+
+        assert_eq!(actual.dim(), expected.dim());
+        for (a, e) in actual.rows().into_iter().zip(expected.rows()) {
+            let (a, e) = (a.to_vec(), e.to_vec());
+            assert!(cosine_similarity(&a, &e) > 0.9999);
+            let max_difference = a
+                .iter()
+                .zip(&e)
+                .map(|(a, e)| (a - e).abs())
+                .fold(0.0, f32::max);
+            assert!(
+                max_difference < 1e-4,
+                "embeddings differ by {max_difference}"
+            );
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! Embedding behavior on a fake model backend, so that no ONNX model or tokenizer file is
+//! needed.
+
+#[allow(dead_code)]
+#[path = "../src/test_support.rs"]
+mod test_support;
+
+use ndarray::{Array2, ArrayView1};
+use onnx_inference::backend::{FAKE_SENTENCE_OUTPUT, FakeBackend};
+use onnx_inference::cache::EmbeddingCache;
+use onnx_inference::pool::GeneratorPool;
+use onnx_inference::{
+    EmbeddingConfig, EmbeddingError, EmbeddingGenerator, Padding, Pooling, cosine_similarity,
+    similarity,
+};
+use test_support::{bert_tokenizer, token_id};
+
+const DIMENSION: usize = 8;
+
+fn generator(config: EmbeddingConfig) -> EmbeddingGenerator {
+    EmbeddingGenerator::with_backend(bert_tokenizer(), FakeBackend::new(DIMENSION), config).unwrap()
+}
+
+fn texts(texts: &[&str]) -> Vec<String> {
+    texts.iter().map(|t| t.to_string()).collect()
+}
+
+fn assert_close(actual: ArrayView1<f32>, expected: ArrayView1<f32>) {
+    for (a, e) in actual.iter().zip(expected) {
+        assert!((a - e).abs() < 1e-5, "{actual} != {expected}");
+    }
+}
+
+#[test]
+fn mean_pooling_averages_the_tokens_and_special_tokens() {
+    let fake = FakeBackend::new(DIMENSION);
+    let mut generator = generator(EmbeddingConfig::default());
+
+    let embeddings = generator.generate_embeddings(&texts(&["the fox"])).unwrap();
+
+    let tokens = ["[CLS]", "the", "fox", "[SEP]"];
+    let expected: Vec<f32> = (0..DIMENSION)
+        .map(|i| {
+            let sum: f32 = tokens
+                .iter()
+                .map(|t| fake.token_embedding(token_id(t).into())[i])
+                .sum();
+            sum / tokens.len() as f32
+        })
+        .collect();
+    assert_eq!(embeddings.dim(), (1, DIMENSION));
+    assert_close(embeddings.row(0), ArrayView1::from(&expected));
+}
+
+#[test]
+fn padding_does_not_change_embeddings() {
+    let mut generator = generator(EmbeddingConfig::default());
+    let alone = generator.generate_embeddings(&texts(&["fox"])).unwrap();
+    let batched = generator
+        .generate_embeddings(&texts(&["the fox ran into the jungle", "fox"]))
+        .unwrap();
+
+    let mut fixed = self::generator(EmbeddingConfig::default().with_padding(Padding::Fixed(16)));
+    let padded = fixed.generate_embeddings(&texts(&["fox"])).unwrap();
+
+    assert_close(batched.row(1), alone.row(0));
+    assert_close(padded.row(0), alone.row(0));
+}
+
+#[test]
+fn cls_pooling_reads_the_first_token() {
+    let fake = FakeBackend::new(DIMENSION);
+    let mut generator = generator(EmbeddingConfig::default().with_pooling(Pooling::Cls));
+
+    let embeddings = generator
+        .generate_embeddings(&texts(&["the fox", "jungle"]))
+        .unwrap();
+
+    let cls = fake.token_embedding(token_id("[CLS]").into());
+    assert_close(embeddings.row(0), ArrayView1::from(&cls));
+    assert_close(embeddings.row(1), ArrayView1::from(&cls));
+}
+
+#[test]
+fn normalized_embeddings_score_like_cosine_similarity() {
+    let mut raw = generator(EmbeddingConfig::default());
+    let mut normalized = generator(EmbeddingConfig::default().with_normalization(true));
+    let corpus = texts(&["the fox ran", "into the jungle"]);
+
+    let raw = raw.generate_embeddings(&corpus).unwrap();
+    let unit = normalized.generate_embeddings(&corpus).unwrap();
+
+    for row in unit.rows() {
+        assert!((row.dot(&row) - 1.0).abs() < 1e-5);
+    }
+    let cosine = cosine_similarity(&raw.row(0).to_vec(), &raw.row(1).to_vec());
+    let dot = similarity(&unit.row(0).to_vec(), &unit.row(1).to_vec(), true);
+    assert!((cosine - dot).abs() < 1e-5);
+    assert!((cosine_similarity(&raw.row(0).to_vec(), &raw.row(0).to_vec()) - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn pooled_output_is_used_as_is() {
+    let mut pooled = EmbeddingGenerator::with_backend(
+        bert_tokenizer(),
+        FakeBackend::new(DIMENSION).with_sentence_output(),
+        EmbeddingConfig::default()
+            .with_output(FAKE_SENTENCE_OUTPUT)
+            .with_pooling(Pooling::Cls),
+    )
+    .unwrap();
+    let mut generator = generator(EmbeddingConfig::default());
+    let corpus = texts(&["the fox", "the fox ran into the jungle"]);
+
+    let from_output = pooled.generate_embeddings(&corpus).unwrap();
+    let mean_pooled = generator.generate_embeddings(&corpus).unwrap();
+
+    assert_eq!(pooled.dimension().unwrap(), DIMENSION);
+    for (a, b) in from_output.rows().into_iter().zip(mean_pooled.rows()) {
+        assert_close(a, b);
+    }
+}
+
+#[test]
+fn inputs_are_bound_by_name() {
+    let backend = FakeBackend::new(DIMENSION).with_inputs(&["attention_mask", "input_ids"]);
+    let mut generator =
+        EmbeddingGenerator::with_backend(bert_tokenizer(), backend, EmbeddingConfig::default())
+            .unwrap();
+
+    assert_eq!(generator.signature().inputs.len(), 2);
+    assert!(generator.session_report().is_none());
+    assert_eq!(
+        generator
+            .generate_embeddings(&texts(&["fox"]))
+            .unwrap()
+            .dim(),
+        (1, DIMENSION)
+    );
+
+    let backend = FakeBackend::new(DIMENSION).with_inputs(&["input_ids", "pixel_values"]);
+    let unsupported =
+        EmbeddingGenerator::with_backend(bert_tokenizer(), backend, EmbeddingConfig::default());
+    assert!(matches!(unsupported, Err(EmbeddingError::ModelLoad { .. })));
+}
+
+#[test]
+fn input_errors_are_typed() {
+    let mut generator = generator(
+        EmbeddingConfig::default()
+            .with_max_length(4)
+            .with_truncation(false),
+    );
+
+    let too_long = generator.generate_embeddings(&texts(&["the fox ran"]));
+    let empty = generator.generate_embeddings(&[]);
+
+    assert!(matches!(
+        too_long,
+        Err(EmbeddingError::InputTooLong {
+            tokens: 5,
+            max_length: 4
+        })
+    ));
+    assert!(matches!(empty, Err(EmbeddingError::EmptyInput)));
+    assert!(too_long.unwrap_err().is_input_error());
+    assert!(generator.generate_embeddings(&texts(&["the fox"])).is_ok());
+}
+
+#[test]
+fn cache_serves_repeated_texts() {
+    let mut uncached = generator(EmbeddingConfig::default());
+    let mut generator =
+        generator(EmbeddingConfig::default()).with_cache(EmbeddingCache::new("fake", 16));
+
+    generator
+        .generate_embeddings(&texts(&["the fox", "jungle"]))
+        .unwrap();
+    let cached = generator
+        .generate_embeddings(&texts(&["jungle", "the  fox", "ran"]))
+        .unwrap();
+
+    // Texts are matched exactly: whitespace is significant to byte-level tokenizers.
+    let stats = generator.cache().unwrap().stats();
+    assert_eq!((stats.hits, stats.misses), (1, 4));
+    let expected = uncached
+        .generate_embeddings(&texts(&["jungle", "the  fox", "ran"]))
+        .unwrap();
+    for (a, b) in cached.rows().into_iter().zip(expected.rows()) {
+        assert_close(a, b);
+    }
+}
+
+#[test]
+fn pool_keeps_input_order_across_parallel_batches() {
+    let corpus = texts(&["the", "fox", "ran", "into", "the jungle", "fox ran"]);
+    let expected: Array2<f32> = generator(EmbeddingConfig::default())
+        .generate_embeddings(&corpus)
+        .unwrap();
+    let pool = GeneratorPool::from_generators(vec![
+        generator(EmbeddingConfig::default()),
+        generator(EmbeddingConfig::default()),
+    ]);
+
+    let embeddings = pool.generate_embeddings_parallel(&corpus, 2).unwrap();
+
+    assert_eq!(embeddings.dim(), expected.dim());
+    for (a, b) in embeddings.rows().into_iter().zip(expected.rows()) {
+        assert_close(a, b);
+    }
+}
+
+#[cfg(feature = "registry")]
+#[tokio::test]
+async fn batcher_fails_only_the_request_with_bad_input() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use onnx_inference::batcher::{Batcher, BatcherConfig};
+
+    let config = EmbeddingConfig::default()
+        .with_max_length(4)
+        .with_truncation(false);
+    let pool = GeneratorPool::from_generators(vec![generator(config.clone())]);
+    let batcher = Batcher::spawn(
+        Arc::new(pool),
+        BatcherConfig {
+            max_wait: Duration::from_millis(200),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let (bad, good) = tokio::join!(
+        batcher.embed(texts(&["the fox ran"])),
+        batcher.embed(texts(&["the", "fox"])),
+    );
+
+    assert_eq!(batcher.metrics().batches, 1);
+    let bad = bad.err().unwrap();
+    assert!(matches!(
+        bad.downcast_ref::<EmbeddingError>(),
+        Some(EmbeddingError::InputTooLong { .. })
+    ));
+    let good = good.unwrap();
+    let expected = generator(config)
+        .generate_embeddings(&texts(&["the", "fox"]))
+        .unwrap();
+    assert_eq!(good.tokens, 6);
+    for (a, b) in good.embeddings.rows().into_iter().zip(expected.rows()) {
+        assert_close(a, b);
+    }
+}
+
+#[cfg(feature = "registry")]
+#[tokio::test]
+async fn batcher_runs_a_request_that_overflowed_a_batch() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use onnx_inference::batcher::{Batcher, BatcherConfig};
+
+    let pool = GeneratorPool::from_generators(vec![
+        generator(EmbeddingConfig::default()),
+        generator(EmbeddingConfig::default()),
+    ]);
+    let batcher = Batcher::spawn(
+        Arc::new(pool),
+        BatcherConfig {
+            max_batch_size: 2,
+            max_wait: Duration::from_millis(200),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let first = tokio::spawn({
+        let batcher = batcher.clone();
+        async move { batcher.embed(texts(&["the"])).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    // Does not fit next to the first request, so it is carried over to the next batch.
+    let second = tokio::time::timeout(
+        Duration::from_secs(3),
+        batcher.embed(texts(&["fox", "ran"])),
+    )
+    .await
+    .expect("the carried request was not run");
+
+    assert_eq!(second.unwrap().embeddings.nrows(), 2);
+    assert_eq!(first.await.unwrap().unwrap().embeddings.nrows(), 1);
+}